@@ -0,0 +1,35 @@
+//! Times walking a quoted list of `n` elements with `n` top-level
+//! `(define l (cdr l))` for growing `n`. With shared cons cells the time per
+//! element stays flat, a quadratic list implementation doubles it with every
+//! row.
+//!
+//! Run with `cargo bench --bench lists`.
+use skim::run::run;
+use std::env::temp_dir;
+use std::fs;
+use std::time::{Duration, Instant};
+
+const SIZES: [usize; 4] = [25_000, 50_000, 100_000, 200_000];
+const RUNS: usize = 5;
+
+fn main() {
+    let path = temp_dir().join("skim-bench-lists.skim");
+    for &n in SIZES.iter() {
+        let elements: Vec<String> = (0..n).map(|i| i.to_string()).collect();
+        let mut script = format!("(define l (quote ({})))\n", elements.join(" "));
+        script.push_str(&"(define l (cdr l))\n".repeat(n));
+        fs::write(&path, script).expect("could not write benchmark script");
+        let mut best = Duration::from_secs(u64::MAX);
+        for _ in 0..RUNS {
+            let start = Instant::now();
+            run(path.to_str().unwrap(), false);
+            best = best.min(start.elapsed());
+        }
+        println!(
+            "walking {} elements: {:?}, {:.1} ns per element",
+            n,
+            best,
+            best.as_nanos() as f64 / n as f64
+        );
+    }
+}
@@ -0,0 +1,42 @@
+//! Variable-heavy workload: many calls to procedures with several parameters,
+//! so nearly all of the time is spent binding and looking up symbols.
+//!
+//! Run with `cargo bench --bench variables`.
+use skim::run::run;
+use std::env::temp_dir;
+use std::fs;
+use std::time::Instant;
+
+const CALLS: usize = 20_000;
+const RUNS: usize = 5;
+
+fn script() -> String {
+    let mut code = String::new();
+    code.push_str("(define alpha 1)\n(define beta 2)\n(define gamma 3)\n(define delta 4)\n");
+    code.push_str("(define (sum4 a b c d) (+ a b c d (- a b) (- c d)))\n");
+    code.push_str("(define (nest x y) (sum4 x y (sum4 y x alpha beta) (sum4 gamma delta x y)))\n");
+    for _ in 0..CALLS {
+        code.push_str("(nest alpha (nest beta gamma))\n");
+    }
+    code
+}
+
+fn main() {
+    let path = temp_dir().join("skim-bench-variables.skim");
+    fs::write(&path, script()).expect("could not write benchmark script");
+    let path = path.to_str().unwrap();
+
+    let mut best = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        run(path, false);
+        let elapsed = start.elapsed();
+        best = Some(best.map_or(elapsed, |b: std::time::Duration| b.min(elapsed)));
+    }
+    println!(
+        "variables: {} calls, best of {} runs: {:?}",
+        CALLS,
+        RUNS,
+        best.unwrap()
+    );
+}
@@ -1,4 +1,6 @@
 use std::fmt::Display;
+use std::rc::Rc;
+use std::time::Duration;
 
 
 #[derive(Clone)]
@@ -6,14 +8,15 @@ pub struct Meta {
     pub line: usize,
     pub position: usize,
     pub token_length: usize,
-    pub file_name: Option<String>,
-    pub code: String,
+    pub file_name: Option<Rc<str>>,
+    pub code: Rc<str>,
 }
 
 #[derive(Clone)]
 pub enum Arity {
     Exact(usize),
     AtLeast(usize),
+    OneOf(Vec<Arity>),
 }
 
 #[derive(Clone)]
@@ -21,6 +24,12 @@ pub enum Condition {
     Syntax(String),
     Arity(Arity, usize),
     Type(String, String),
+    /// Memory ran out, at the heap limit if there is one.
+    OutOfMemory(Option<usize>),
+    StepLimit(u64),
+    RecursionLimit(usize),
+    TimeLimit(Duration),
+    Io(String),
     Other(String), // TODO: replace this with meaningful variants
 }
 
@@ -30,13 +39,19 @@ pub struct Exn {
 }
 
 impl Meta {
-    pub fn new(line: usize, pos: usize, length: usize, file: Option<String>, code: String) -> Meta {
+    pub fn new(
+        line: usize,
+        pos: usize,
+        length: usize,
+        file: Option<Rc<str>>,
+        code: Rc<str>,
+    ) -> Meta {
         Meta {
-            line: line,
+            line,
             position: pos,
             token_length: length,
             file_name: file,
-            code: code,
+            code,
         }
     }
 
@@ -46,7 +61,7 @@ impl Meta {
             position: 0,
             token_length: 0,
             file_name: None,
-            code: "".to_owned(),
+            code: "".into(),
         }
     }
 }
@@ -54,7 +69,7 @@ impl Meta {
 impl Exn {
     pub fn new(meta: Meta, cond: Condition) -> Exn {
         Exn {
-            meta: meta,
+            meta,
             condition: cond,
         }
     }
@@ -77,9 +92,36 @@ impl Exn {
         )
     }
 
+    pub fn io(meta: Meta, msg: &str) -> Exn {
+        Exn::new(meta, Condition::Io(msg.to_string()))
+    }
+
     pub fn other(meta: Meta, msg: &str) -> Exn {
         Exn::new(meta, Condition::Other(msg.to_string()))
     }
+
+    pub fn condition(&self) -> &Condition {
+        &self.condition
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Arity::Exact(a) => write!(f, "{}", a),
+            Arity::AtLeast(a) => write!(f, "at least {}", a),
+            Arity::OneOf(arities) => {
+                let arities: Vec<String> = arities.iter().map(Arity::to_string).collect();
+                write!(f, "{}", arities.join(" or "))
+            }
+        }
+    }
+}
+
+/// Indents all lines of `text` but the first by `column` columns, for text
+/// that is embedded in a line at that column.
+fn hang(text: &str, column: usize) -> String {
+    text.replace('\n', &format!("\n{}", " ".repeat(column)))
 }
 
 impl Display for Exn {
@@ -87,16 +129,33 @@ impl Display for Exn {
         let (title, msg) = match self.condition.clone() {
             Condition::Arity(expected, found) => (
                 "wrong number of arguments",
-                match expected {
-                    Arity::Exact(a) => format!("expected {} arguments, found {}", a, found),
-                    Arity::AtLeast(a) => format!("expected at least {} arguments, found {}", a, found),
-                },
+                format!("expected {} arguments, found {}", expected, found),
             ),
             Condition::Syntax(msg) => ("wrong syntax", msg),
-            Condition::Type(found, expected) => (
-                "mismatched types",
-                format!("expected {}, found {}", expected, found),
+            Condition::Type(expected, found) => {
+                let prefix = format!("expected {}, found ", expected);
+                ("mismatched types", format!("{}{}", prefix, hang(&found, prefix.len())))
+            }
+            Condition::OutOfMemory(Some(limit)) => (
+                "out of memory",
+                format!("heap limit of {} bytes exceeded", limit),
+            ),
+            Condition::OutOfMemory(None) => {
+                ("out of memory", "could not allocate memory".to_string())
+            }
+            Condition::StepLimit(limit) => (
+                "step limit exceeded",
+                format!("evaluation took more than {} steps", limit),
+            ),
+            Condition::RecursionLimit(limit) => (
+                "recursion limit exceeded",
+                format!("more than {} nested procedure calls", limit),
+            ),
+            Condition::TimeLimit(limit) => (
+                "time limit exceeded",
+                format!("evaluation took longer than {} ms", limit.as_millis()),
             ),
+            Condition::Io(msg) => ("i/o error", msg),
             Condition::Other(msg) => ("unknown", msg),
         };
         let mut file = true;
@@ -111,18 +170,21 @@ impl Display for Exn {
             file = false;
             format!("exception: {}", title)
         };
-        let body = if file {
-            let mut body = format!("{} |\n{} | {}\n{} |", space, self.meta.line, self.meta.code, space);
+        let body = if file || !self.meta.code.is_empty() {
+            let line = if file { self.meta.line.to_string() } else { space.clone() };
+            let mut body = format!("{} |\n{} | {}\n{} |", space, line, self.meta.code, space);
             let mut pointer = String::new();
             for _ in 0..self.meta.position { pointer.push(' '); }
             for _ in 0..self.meta.token_length { pointer.push('^'); }
             pointer.push(' ');
+            let column = self.meta.position + self.meta.token_length + 1;
+            let msg = hang(&msg, column).replace('\n', &format!("\n{} |", space));
             pointer.push_str(&msg);
             pointer.push('\n');
             body.push_str(&pointer);
             body
         } else {
-            todo!()
+            format!("{}\n", msg)
         };
         write!(f, "{}\n{}", header, body)
     }
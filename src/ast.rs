@@ -1,18 +1,37 @@
+use crate::gc::{Gc, Trace};
 use crate::runtime::Env;
+use crate::symbol::Symbol;
+use crate::port::Port;
+use crate::record::{Record, RecordType};
+use crate::table::HashTable;
+use crate::write::{self, Sharing};
 use crate::Exception::*;
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::fmt::Display;
-use std::rc::Rc;
 
-#[derive(Clone)]
+/// A cons cell. Cells are shared between all lists they are part of, so
+/// `car`, `cdr` and `cons` are O(1).
 pub struct LispCell {
-    pub car: Box<Item>,
-    pub cdr: Box<Item>,
+    car: RefCell<Item>,
+    cdr: RefCell<Item>,
+}
+
+/// The parameters of a lambda: `(a b)`, `(a b . rest)` or just `args`.
+pub struct Params {
+    pub required: Vec<Symbol>,
+    pub rest: Option<Symbol>,
+}
+
+/// One way of calling a lambda. Lambdas made with `case-lambda` have several.
+pub struct Clause {
+    pub params: Params,
+    pub body: Item,
 }
 
-#[derive(Clone)]
 pub struct Lambda {
-    pub params: Rc<Vec<String>>,
-    pub body: Rc<Item>,
+    pub clauses: Vec<Clause>,
+    pub env: Env,
 }
 
 pub type Primitive = fn(env: &mut Env, meta: Meta, params: Item) -> Result<Item, Exn>;
@@ -23,12 +42,20 @@ pub enum Exp {
     Boolean(bool),
     Number(f64),
     Char(char),
-    Symbol(String),
-    String(String),
-    Vector(Vec<Exp>),
-    Pair(LispCell),
+    Symbol(Symbol),
+    String(Gc<String>),
+    Vector(Gc<RefCell<Vec<Exp>>>),
+    HashTable(Gc<HashTable>),
+    Record(Gc<Record>),
+    RecordType(Gc<RecordType>),
+    Port(Gc<Port>),
+    Eof,
+    /// What procedures that are only called for their effect return.
+    Unspecified,
+    Environment(Env),
+    Pair(Gc<LispCell>),
     Primitive(Primitive),
-    Lambda(Lambda),
+    Lambda(Gc<Lambda>),
 }
 
 #[derive(Clone)]
@@ -39,15 +66,12 @@ pub struct Item {
 
 impl Item {
     pub fn new(meta: Meta, exp: Exp) -> Item {
-        Item {
-            meta: meta,
-            exp: exp,
-        }
+        Item { meta, exp }
     }
 
     pub fn cons(meta: Meta, car: Item, cdr: Item) -> Item {
         Item {
-            meta: meta,
+            meta,
             exp: Exp::Pair(cons(car, cdr)),
         }
     }
@@ -55,87 +79,132 @@ impl Item {
     pub fn prim(meta: Meta, fun: Primitive) -> Item {
         Self::new(meta, Exp::Primitive(fun))
     }
+
+    /// Builds a proper list of `items`.
+    pub fn list(meta: Meta, items: Vec<Item>) -> Item {
+        let mut list = Item::new(meta.clone(), Exp::Nil);
+        for item in items.into_iter().rev() {
+            list = Item::cons(meta.clone(), item, list);
+        }
+        list
+    }
+}
+
+impl Params {
+    pub fn parse(formals: &Item) -> Result<Params, Exn> {
+        let mut required = Vec::new();
+        let mut rest = formals.exp.clone();
+        while let Exp::Pair(cell) = rest {
+            let param = cell.car();
+            match param.exp {
+                Exp::Symbol(s) => required.push(s),
+                x => return Err(Exn::typ(param.meta, "symbol", &x.type_name())),
+            }
+            rest = cell.cdr().exp;
+        }
+        let rest = match rest {
+            Exp::Nil => None,
+            Exp::Symbol(s) => Some(s),
+            x => return Err(Exn::typ(formals.meta.clone(), "symbol", &x.type_name())),
+        };
+        Ok(Params { required, rest })
+    }
+
+    pub fn accepts(&self, num_args: usize) -> bool {
+        match self.rest {
+            Some(_) => num_args >= self.required.len(),
+            None => num_args == self.required.len(),
+        }
+    }
+
+    pub fn arity(&self) -> Arity {
+        match self.rest {
+            Some(_) => Arity::AtLeast(self.required.len()),
+            None => Arity::Exact(self.required.len()),
+        }
+    }
+
+    /// Shows how a procedure called `name` with these parameters is called,
+    /// like `(f x . rest)`.
+    pub fn signature(&self, name: &str) -> String {
+        let mut signature = format!("({}", name);
+        for param in &self.required {
+            signature.push(' ');
+            signature.push_str(&param.name());
+        }
+        if let Some(rest) = self.rest {
+            signature.push_str(" . ");
+            signature.push_str(&rest.name());
+        }
+        signature.push(')');
+        signature
+    }
+}
+
+impl Lambda {
+    pub fn arity(&self) -> Arity {
+        match &self.clauses[..] {
+            [clause] => clause.params.arity(),
+            clauses => Arity::OneOf(clauses.iter().map(|c| c.params.arity()).collect()),
+        }
+    }
 }
 
 impl LispCell {
     pub fn new(car: Item, cdr: Item) -> LispCell {
         LispCell {
-            car: Box::new(car),
-            cdr: Box::new(cdr),
+            car: RefCell::new(car),
+            cdr: RefCell::new(cdr),
         }
     }
 
-    pub fn set_cdr(&mut self, cdr: Item) {
-        self.cdr = Box::new(cdr);
+    pub fn car(&self) -> Item {
+        self.car.borrow().clone()
     }
 
-    pub fn append(&mut self, item: Item) -> Result<(), Exn> {
-        let mut cell = self;
-        loop {
-            if let Exp::Nil = cell.cdr.exp {
-                cell.set_cdr(Item::cons(
-                    cell.cdr.meta.clone(),
-                    item,
-                    Item::new(cell.cdr.meta.clone(), Exp::Nil),
-                ));
-                return Ok(());
-            }
-            if let Exp::Pair(ref mut x) = cell.cdr.exp {
-                cell = x;
-            } else {
-                return Err(Exn::other(item.meta, "tried to append to an unproper list"));
-            }
-        }
+    pub fn cdr(&self) -> Item {
+        self.cdr.borrow().clone()
     }
 
-    fn format(&self) -> String {
-        let mut s = String::from("(");
-        s.push_str(&self.format_naked());
-        s.push(')');
-        s
+    pub fn set_car(&self, car: Item) {
+        *self.car.borrow_mut() = car;
     }
 
-    fn format_naked(&self) -> String {
-        let x = self.clone();
-        let mut s = x.car.exp.format();
-        match x.cdr.exp {
-            Exp::Pair(cdr) => {
-                s.push(' ');
-                s.push_str(&cdr.format_naked());
-            }
-            Exp::Nil => {} // do nothing
-            _ => {
-                s.push_str(" . ");
-                s.push_str(&x.cdr.exp.format());
+    pub fn set_cdr(&self, cdr: Item) {
+        *self.cdr.borrow_mut() = cdr;
+    }
+}
+
+impl Drop for LispCell {
+    // drop long lists iteratively instead of recursing once per cell
+    fn drop(&mut self) {
+        let mut next = std::mem::replace(&mut self.cdr.get_mut().exp, Exp::Nil);
+        while let Exp::Pair(cell) = next {
+            if !cell.is_unique() {
+                break;
             }
+            next = std::mem::replace(&mut cell.cdr.borrow_mut().exp, Exp::Nil);
         }
-        s
     }
 }
 
 pub struct ListIter {
-    pub list: Option<LispCell>,
+    pub list: Option<Gc<LispCell>>,
 }
 
 impl Iterator for ListIter {
     type Item = Item;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(list) = self.list.clone() {
-            let item = list.car;
-            if let Exp::Pair(rest) = list.cdr.exp {
-                self.list = Some(rest);
-            } else {
-                self.list = None;
-            }
-            return Some(*item);
-        } else {
-            return None;
+        let cell = self.list.take()?;
+        if let Exp::Pair(rest) = cell.cdr.borrow().exp.clone() {
+            self.list = Some(rest);
         }
+        Some(cell.car())
     }
 }
 
-impl IntoIterator for LispCell {
+impl IntoIterator for Gc<LispCell> {
     type Item = Item;
     type IntoIter = ListIter;
 
@@ -146,37 +215,73 @@ impl IntoIterator for LispCell {
 
 impl Display for Item {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        write!(f, "{}", self.exp.format())
+        write!(f, "{}", self.exp.format(false))
     }
 }
 
 impl Display for Exp {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        write!(f, "{}", self.format())
+        write!(f, "{}", self.format(false))
     }
 }
 
 impl Exp {
-    fn format(&self) -> String {
-        match self {
-            Exp::Nil => "()".to_string(),
-            Exp::Number(s) => s.to_string(),
-            Exp::Symbol(s) => s.to_string(),
-            Exp::Primitive(_) => "primitive function".to_string(),
-            Exp::Pair(x) => x.format(),
-            Exp::Lambda(_) => "lambda function".to_string(),
-            Exp::Boolean(b) => if *b { "#t" } else { "#f" }.to_string(),
-            Exp::Char(c) => c.to_string(),
-            Exp::Vector(vec) => {
-                let mut str = String::from("[");
-                for exp in vec {
-                    str.push_str(&exp.format());
-                    str.push(' ');
+    /// The external representation of this value, as printed by `write` if
+    /// `write` is true and by `display` otherwise. Only `write` escapes strings
+    /// and characters, so they can be read back in.
+    pub(crate) fn format(&self, write: bool) -> String {
+        write::to_string(self, write, Sharing::Cycles)
+    }
+
+    /// The representation of this value printed by `write`.
+    pub fn written(&self) -> String {
+        self.format(true)
+    }
+
+    /// Returns true for proper lists, which end in `()`. Cyclic lists are not
+    /// proper lists.
+    pub fn is_list(&self) -> bool {
+        let mut slow = self.clone();
+        let mut fast = self.clone();
+        loop {
+            for _ in 0..2 {
+                fast = match fast {
+                    Exp::Nil => return true,
+                    Exp::Pair(cell) => cell.cdr().exp,
+                    _ => return false,
+                };
+            }
+            if let Exp::Pair(cell) = slow {
+                slow = cell.cdr().exp;
+            }
+            if let (Exp::Pair(a), Exp::Pair(b)) = (&slow, &fast) {
+                if Gc::ptr_eq(a, b) {
+                    return false;
+                }
+            }
+        }
+    }
+
+    /// Returns true if the cdrs of this value lead back to a pair that came
+    /// before, so that following them never ends.
+    pub fn is_circular(&self) -> bool {
+        let mut slow = self.clone();
+        let mut fast = self.clone();
+        loop {
+            for _ in 0..2 {
+                fast = match fast {
+                    Exp::Pair(cell) => cell.cdr().exp,
+                    _ => return false,
+                };
+            }
+            if let Exp::Pair(cell) = slow {
+                slow = cell.cdr().exp;
+            }
+            if let (Exp::Pair(a), Exp::Pair(b)) = (&slow, &fast) {
+                if Gc::ptr_eq(a, b) {
+                    return true;
                 }
-                str.push(']');
-                str
             }
-            Exp::String(s) => s.to_string(),
         }
     }
 
@@ -191,12 +296,193 @@ impl Exp {
             Exp::Boolean(_) => "boolean",
             Exp::Char(_) => "char",
             Exp::Vector(_) => "vector",
+            Exp::HashTable(_) => "hash-table",
+            Exp::Record(record) => return record.rtd.name.clone(),
+            Exp::RecordType(_) => "record-type",
+            Exp::Port(_) => "port",
+            Exp::Eof => "eof-object",
+            Exp::Unspecified => "unspecified",
+            Exp::Environment(_) => "environment",
             Exp::String(_) => "string",
         }
         .to_string()
     }
 }
 
-pub fn cons(car: Item, cdr: Item) -> LispCell {
-    LispCell::new(car, cdr)
+impl Exp {
+    /// Identity as defined by `eq?` (R7RS 6.1). Heap objects are compared by
+    /// address and symbols by their interned id. Numbers and characters are
+    /// immediate values in skim, so they are compared like `eqv?` does.
+    pub fn is_eq(&self, other: &Exp) -> bool {
+        match (self, other) {
+            (Exp::Nil, Exp::Nil) => true,
+            (Exp::Boolean(a), Exp::Boolean(b)) => a == b,
+            (Exp::Number(a), Exp::Number(b)) => a.to_bits() == b.to_bits(),
+            (Exp::Char(a), Exp::Char(b)) => a == b,
+            (Exp::Symbol(a), Exp::Symbol(b)) => a == b,
+            (Exp::String(a), Exp::String(b)) => Gc::ptr_eq(a, b),
+            (Exp::Vector(a), Exp::Vector(b)) => Gc::ptr_eq(a, b),
+            (Exp::HashTable(a), Exp::HashTable(b)) => Gc::ptr_eq(a, b),
+            (Exp::Record(a), Exp::Record(b)) => Gc::ptr_eq(a, b),
+            (Exp::RecordType(a), Exp::RecordType(b)) => Gc::ptr_eq(a, b),
+            (Exp::Port(a), Exp::Port(b)) => Gc::ptr_eq(a, b),
+            (Exp::Eof, Exp::Eof) => true,
+            (Exp::Unspecified, Exp::Unspecified) => true,
+            (Exp::Environment(a), Exp::Environment(b)) => a.addr() == b.addr(),
+            (Exp::Pair(a), Exp::Pair(b)) => Gc::ptr_eq(a, b),
+            (Exp::Lambda(a), Exp::Lambda(b)) => Gc::ptr_eq(a, b),
+            (Exp::Primitive(a), Exp::Primitive(b)) => *a as usize == *b as usize,
+            _ => false,
+        }
+    }
+
+    /// Equivalence as defined by `eqv?` (R7RS 6.1). In skim this is the same
+    /// as `eq?`, since numbers and characters are immediate values that `eq?`
+    /// already compares by value: numbers by their bits, so `0` and `-0` are
+    /// not equivalent while two NaNs with the same bits are.
+    pub fn is_eqv(&self, other: &Exp) -> bool {
+        self.is_eq(other)
+    }
+
+    /// Structural equality as defined by `equal?` (R7RS 6.1). Pairs and vectors
+    /// are compared element by element and strings by content, everything else
+    /// with `eqv?`. Terminates on cyclic structures: two cells that are compared
+    /// a second time are assumed to be equal.
+    pub fn is_equal(&self, other: &Exp) -> bool {
+        let mut seen = HashSet::new();
+        let mut todo = vec![(self.clone(), other.clone())];
+        while let Some((a, b)) = todo.pop() {
+            match (&a, &b) {
+                (Exp::Pair(x), Exp::Pair(y)) => {
+                    if Gc::ptr_eq(x, y) || !seen.insert((x.addr(), y.addr())) {
+                        continue;
+                    }
+                    todo.push((x.cdr().exp, y.cdr().exp));
+                    todo.push((x.car().exp, y.car().exp));
+                }
+                (Exp::Vector(x), Exp::Vector(y)) => {
+                    if Gc::ptr_eq(x, y) || !seen.insert((x.addr(), y.addr())) {
+                        continue;
+                    }
+                    let (x, y) = (x.borrow(), y.borrow());
+                    if x.len() != y.len() {
+                        return false;
+                    }
+                    todo.extend(x.iter().cloned().zip(y.iter().cloned()));
+                }
+                (Exp::String(x), Exp::String(y)) => {
+                    if **x != **y {
+                        return false;
+                    }
+                }
+                _ => {
+                    if !a.is_eqv(&b) {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Visits the heap objects referenced by this value, see `Trace::trace`.
+    pub(crate) fn trace(&self, visit: &mut dyn FnMut(usize)) {
+        match self {
+            Exp::Pair(cell) => visit(cell.addr()),
+            Exp::String(s) => visit(s.addr()),
+            Exp::Vector(vec) => visit(vec.addr()),
+            Exp::HashTable(table) => visit(table.addr()),
+            Exp::Record(record) => visit(record.addr()),
+            Exp::RecordType(rtd) => visit(rtd.addr()),
+            Exp::Port(port) => visit(port.addr()),
+            Exp::Environment(env) => visit(env.addr()),
+            Exp::Lambda(lambda) => visit(lambda.addr()),
+            Exp::Nil
+            | Exp::Eof
+            | Exp::Unspecified
+            | Exp::Boolean(_)
+            | Exp::Number(_)
+            | Exp::Char(_)
+            | Exp::Symbol(_)
+            | Exp::Primitive(_) => {}
+        }
+    }
+}
+
+impl Trace for LispCell {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        if let (Ok(car), Ok(cdr)) = (self.car.try_borrow(), self.cdr.try_borrow()) {
+            car.exp.trace(visit);
+            cdr.exp.trace(visit);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn clear(&self) {
+        if let (Ok(mut car), Ok(mut cdr)) = (self.car.try_borrow_mut(), self.cdr.try_borrow_mut()) {
+            car.exp = Exp::Nil;
+            cdr.exp = Exp::Nil;
+        }
+    }
+}
+
+impl Trace for String {
+    fn trace(&self, _: &mut dyn FnMut(usize)) -> bool {
+        true
+    }
+
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl Trace for RefCell<Vec<Exp>> {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        if let Ok(vec) = self.try_borrow() {
+            for exp in vec.iter() {
+                exp.trace(visit);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut vec) = self.try_borrow_mut() {
+            vec.clear();
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        self.borrow().capacity() * std::mem::size_of::<Exp>()
+    }
+}
+
+impl Trace for Lambda {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        for clause in &self.clauses {
+            clause.body.exp.trace(visit);
+        }
+        visit(self.env.addr());
+        true
+    }
+}
+
+/// Names of the characters that are written as `#\\name`.
+pub(crate) const CHAR_NAMES: [(&str, char); 8] = [
+    ("space", ' '),
+    ("newline", '\n'),
+    ("tab", '\t'),
+    ("return", '\r'),
+    ("null", '\0'),
+    ("alarm", '\u{7}'),
+    ("backspace", '\u{8}'),
+    ("delete", '\u{7f}'),
+];
+
+pub fn cons(car: Item, cdr: Item) -> Gc<LispCell> {
+    Gc::new(LispCell::new(car, cdr))
 }
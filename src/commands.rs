@@ -0,0 +1,306 @@
+//! The REPL's meta-commands, which start with a comma, like `,help`. They
+//! inspect and control the session rather than being evaluated.
+use crate::ast::*;
+use crate::gc;
+use crate::helper::is_keyword;
+use crate::library::BUILTIN;
+use crate::parser::{lex, parse};
+use crate::repl::Session;
+use crate::run::exec_file;
+use crate::runtime::*;
+use crate::sandbox::sandbox_env;
+use crate::symbol::Symbol;
+use crate::trace;
+use crate::Exception::*;
+use std::time::Instant;
+
+struct Command {
+    name: &'static str,
+    args: &'static str,
+    help: &'static str,
+    run: fn(&mut Session, &str) -> Result<(), Exn>,
+}
+
+const COMMANDS: [Command; 11] = [
+    Command {
+        name: "help",
+        args: "",
+        help: "show this list",
+        run: help,
+    },
+    Command {
+        name: "doc",
+        args: "name",
+        help: "describe what name is bound to",
+        run: doc,
+    },
+    Command {
+        name: "time",
+        args: "expr ...",
+        help: "evaluate and report the time and allocations it took",
+        run: time,
+    },
+    Command {
+        name: "expand",
+        args: "expr",
+        help: "show an expression the way the evaluator sees it",
+        run: expand,
+    },
+    Command {
+        name: "env",
+        args: "[all]",
+        help: "list the session's bindings, or all of them",
+        run: env,
+    },
+    Command {
+        name: "load",
+        args: "file",
+        help: "evaluate a file",
+        run: load,
+    },
+    Command {
+        name: "reset",
+        args: "",
+        help: "start over with a fresh environment",
+        run: reset,
+    },
+    Command {
+        name: "trace",
+        args: "[name ...]",
+        help: "show calls of procedures, or list the traced ones",
+        run: trace,
+    },
+    Command {
+        name: "untrace",
+        args: "[name ...]",
+        help: "stop showing calls of procedures, or of all of them",
+        run: untrace,
+    },
+    Command {
+        name: "quit",
+        args: "",
+        help: "end the session",
+        run: quit,
+    },
+    Command {
+        name: "q",
+        args: "",
+        help: "the same as ,quit",
+        run: quit,
+    },
+];
+
+/// The names of all meta-commands, with their comma.
+pub(crate) fn names() -> impl Iterator<Item = String> {
+    COMMANDS.iter().map(|command| format!(",{}", command.name))
+}
+
+/// Returns true if `input` is a meta-command rather than an expression.
+pub(crate) fn is_command(input: &str) -> bool {
+    input.trim_start().starts_with(',')
+}
+
+/// Runs the meta-command `input`.
+pub(crate) fn run(session: &mut Session, input: &str) -> Result<(), Exn> {
+    let input = input.trim().trim_start_matches(',');
+    let (name, args) = match input.find(char::is_whitespace) {
+        Some(i) => (&input[..i], input[i..].trim()),
+        None => (input, ""),
+    };
+    match COMMANDS.iter().find(|command| command.name == name) {
+        Some(command) => (command.run)(session, args),
+        None => Err(error(&format!("unknown command ,{}, try ,help", name))),
+    }
+}
+
+fn error(msg: &str) -> Exn {
+    Exn::other(Meta::empty(), msg)
+}
+
+/// Parses all expressions in `code`.
+fn parse_all(code: &str) -> Result<Vec<Item>, Exn> {
+    let tokens = lex(code, None);
+    let mut items = Vec::new();
+    let mut rest = &tokens[..];
+    while !rest.is_empty() {
+        let (item, next) = parse(rest)?;
+        items.push(item);
+        rest = next;
+    }
+    Ok(items)
+}
+
+/// Returns the names in `args`, which must not be empty if `required`.
+fn names_arg(args: &str, required: bool) -> Result<Vec<Symbol>, Exn> {
+    let names: Vec<Symbol> = args.split_whitespace().map(Symbol::intern).collect();
+    if required && names.is_empty() {
+        return Err(Exn::arity(Meta::empty(), 1, 0));
+    }
+    Ok(names)
+}
+
+fn lookup(session: &Session, name: Symbol) -> Result<Item, Exn> {
+    session
+        .env
+        .get(name)
+        .ok_or_else(|| error(&format!("{} is not bound", name.name())))
+}
+
+fn help(_: &mut Session, _: &str) -> Result<(), Exn> {
+    let usage: Vec<String> = COMMANDS
+        .iter()
+        .map(|command| format!(",{} {}", command.name, command.args))
+        .collect();
+    let width = usage.iter().map(|usage| usage.len()).max().unwrap_or(0);
+    for (usage, command) in usage.iter().zip(COMMANDS.iter()) {
+        println!("{:width$}  {}", usage, command.help, width = width);
+    }
+    Ok(())
+}
+
+fn doc(session: &mut Session, args: &str) -> Result<(), Exn> {
+    for name in names_arg(args, true)? {
+        let value = lookup(session, name)?;
+        let libraries: Vec<&str> = BUILTIN
+            .iter()
+            .filter(|(_, prims)| prims.iter().any(|(prim, _)| *prim == &*name.name()))
+            .map(|(library, _)| *library)
+            .collect();
+        match &value.exp {
+            Exp::Primitive(_) if is_keyword(&name.name()) => {
+                println!("{} is a special form", name.name())
+            }
+            Exp::Primitive(_) => println!("{} is a primitive procedure", name.name()),
+            Exp::Lambda(lambda) => {
+                println!(
+                    "{} is a procedure taking {} arguments",
+                    name.name(),
+                    lambda.arity()
+                );
+                for clause in &lambda.clauses {
+                    println!("  {}", clause.params.signature(&name.name()));
+                }
+            }
+            x => println!("{} is a {}: {}", name.name(), x.type_name(), x.written()),
+        }
+        if !libraries.is_empty() {
+            println!("  exported by {}", libraries.join(", "));
+        }
+    }
+    Ok(())
+}
+
+fn time(session: &mut Session, args: &str) -> Result<(), Exn> {
+    let before = gc::stats();
+    let start = Instant::now();
+    let mut values = Vec::new();
+    for item in parse_all(args)? {
+        values.push(eval(&mut session.env, &item)?);
+    }
+    let elapsed = start.elapsed();
+    let after = gc::stats();
+    println!(
+        "; {:.3} ms, {} allocations ({} bytes), {} collections",
+        elapsed.as_secs_f64() * 1000.0,
+        after.allocations - before.allocations,
+        after.allocated - before.allocated,
+        after.collections - before.collections,
+    );
+    for value in values {
+        session.print_result(value);
+    }
+    Ok(())
+}
+
+/// There are no macros yet, so this only shows how the reader expands
+/// abbreviations like `'x`.
+fn expand(_: &mut Session, args: &str) -> Result<(), Exn> {
+    for item in parse_all(args)? {
+        println!("{}", item.exp.written());
+    }
+    Ok(())
+}
+
+fn env(session: &mut Session, args: &str) -> Result<(), Exn> {
+    let all = match args {
+        "" => false,
+        "all" => true,
+        _ => return Err(Exn::typ(Meta::empty(), "all", args)),
+    };
+    for name in session.env.names() {
+        let value = match session.env.get(name) {
+            Some(value) => value.exp,
+            None => continue,
+        };
+        // the primitives are the same in every session
+        if !all && matches!(value, Exp::Primitive(_)) {
+            continue;
+        }
+        println!("{}: {}", name.name(), value.type_name());
+    }
+    Ok(())
+}
+
+fn load(session: &mut Session, args: &str) -> Result<(), Exn> {
+    if session.sandbox {
+        return Err(error("files can't be loaded in a sandboxed session"));
+    }
+    if args.is_empty() {
+        return Err(Exn::arity(Meta::empty(), 1, 0));
+    }
+    exec_file(&mut session.env, &Meta::empty(), args)
+}
+
+fn reset(session: &mut Session, _: &str) -> Result<(), Exn> {
+    session.env = if session.sandbox {
+        sandbox_env()
+    } else {
+        default_env()
+    };
+    trace::untrace_all();
+    Ok(())
+}
+
+fn trace(session: &mut Session, args: &str) -> Result<(), Exn> {
+    let names = names_arg(args, false)?;
+    if names.is_empty() {
+        for name in trace::traced() {
+            println!("{}", name);
+        }
+    }
+    for name in names {
+        match lookup(session, name)?.exp {
+            Exp::Lambda(lambda) => trace::trace(&lambda, &name.name()),
+            x => {
+                return Err(Exn::typ(
+                    Meta::empty(),
+                    "procedure defined in lisp",
+                    &x.type_name(),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn untrace(session: &mut Session, args: &str) -> Result<(), Exn> {
+    let names = names_arg(args, false)?;
+    if names.is_empty() {
+        trace::untrace_all();
+    }
+    for name in names {
+        let untraced = match lookup(session, name)?.exp {
+            Exp::Lambda(lambda) => trace::untrace(&lambda),
+            _ => false,
+        };
+        if !untraced {
+            return Err(error(&format!("{} is not traced", name.name())));
+        }
+    }
+    Ok(())
+}
+
+fn quit(session: &mut Session, _: &str) -> Result<(), Exn> {
+    session.quit = true;
+    Ok(())
+}
@@ -0,0 +1,188 @@
+//! Settings of the REPL and where it keeps its files.
+//!
+//! The init file is a program that is evaluated in the REPL's environment
+//! before the first prompt, so it can import libraries and define procedures
+//! for the session. It configures the REPL by defining these variables:
+//!
+//! - `repl-edit-mode`: `'emacs` or `'vi` key bindings
+//! - `repl-prompt`: the prompt, a string
+//! - `repl-theme`: `'dark`, `'light` or `'none` for no colours
+//! - `repl-history-size`: how many inputs the history keeps
+//!
+//! The init file is `$XDG_CONFIG_HOME/skim/config`, or `~/.skimrc` if that
+//! does not exist. The history is kept in `$XDG_STATE_HOME/skim/history`.
+use crate::ast::*;
+use crate::pretty::message;
+use crate::runtime::*;
+use crate::symbol::Symbol;
+use crate::Exception::*;
+use rustyline::EditMode;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+const RESET: &str = "\x1b[0m";
+
+/// The colours of the REPL's output and of the input as it is typed, as ANSI
+/// escape sequences.
+#[derive(Clone, Copy)]
+pub(crate) struct Theme {
+    pub result: &'static str,
+    pub error: &'static str,
+    pub string: &'static str,
+    pub number: &'static str,
+    pub keyword: &'static str,
+    pub comment: &'static str,
+    /// The parenthesis matching the one at the cursor.
+    pub paren: &'static str,
+    pub hint: &'static str,
+}
+
+impl Theme {
+    const DARK: Theme = Theme {
+        result: "\x1b[36m",
+        error: "\x1b[91m",
+        string: "\x1b[32m",
+        number: "\x1b[33m",
+        keyword: "\x1b[95m",
+        comment: "\x1b[90m",
+        paren: "\x1b[1;96m",
+        hint: "\x1b[90m",
+    };
+    const LIGHT: Theme = Theme {
+        result: "\x1b[34m",
+        error: "\x1b[31m",
+        string: "\x1b[32m",
+        number: "\x1b[36m",
+        keyword: "\x1b[35m",
+        comment: "\x1b[90m",
+        paren: "\x1b[1;34m",
+        hint: "\x1b[90m",
+    };
+    const NONE: Theme = Theme {
+        result: "",
+        error: "",
+        string: "",
+        number: "",
+        keyword: "",
+        comment: "",
+        paren: "",
+        hint: "",
+    };
+
+    fn named(name: &str) -> Option<Theme> {
+        match name {
+            "dark" => Some(Theme::DARK),
+            "light" => Some(Theme::LIGHT),
+            "none" => Some(Theme::NONE),
+            _ => None,
+        }
+    }
+
+    /// Returns `text` in the colour `color` of this theme.
+    pub fn paint(&self, color: &str, text: &str) -> String {
+        if color.is_empty() {
+            text.to_string()
+        } else {
+            format!("{}{}{}", color, text, RESET)
+        }
+    }
+}
+
+pub(crate) struct Settings {
+    pub edit_mode: EditMode,
+    pub prompt: String,
+    pub theme: Theme,
+    pub history_size: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            edit_mode: EditMode::Vi,
+            prompt: ">> ".to_string(),
+            // colours would only garble piped output
+            theme: if io::stdout().is_terminal() {
+                Theme::DARK
+            } else {
+                Theme::NONE
+            },
+            history_size: 1000,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings the init file defined in `env`. Those it did not
+    /// define keep their defaults.
+    pub fn read(env: &Env) -> Result<Settings, Exn> {
+        let mut settings = Settings::default();
+        if let Some(mode) = setting(env, "repl-edit-mode") {
+            settings.edit_mode = match &mode.exp {
+                Exp::Symbol(s) if &*s.name() == "emacs" => EditMode::Emacs,
+                Exp::Symbol(s) if &*s.name() == "vi" => EditMode::Vi,
+                x => return Err(Exn::typ(mode.meta, "'emacs or 'vi", &message("", x))),
+            };
+        }
+        if let Some(prompt) = setting(env, "repl-prompt") {
+            settings.prompt = match &prompt.exp {
+                Exp::String(s) => s.to_string(),
+                x => return Err(Exn::typ(prompt.meta, "string", &x.type_name())),
+            };
+        }
+        if let Some(theme) = setting(env, "repl-theme") {
+            settings.theme = match &theme.exp {
+                Exp::Symbol(s) => Theme::named(&s.name()),
+                _ => None,
+            }
+            .ok_or_else(|| {
+                Exn::typ(
+                    theme.meta.clone(),
+                    "'dark, 'light or 'none",
+                    &message("", &theme.exp),
+                )
+            })?;
+        }
+        if let Some(size) = setting(env, "repl-history-size") {
+            settings.history_size = match size.exp {
+                Exp::Number(n) if n >= 0.0 && n.fract() == 0.0 => n as usize,
+                ref x => return Err(Exn::typ(size.meta, "non-negative integer", &message("", x))),
+            };
+        }
+        if !io::stdout().is_terminal() {
+            settings.theme = Theme::NONE;
+        }
+        Ok(settings)
+    }
+}
+
+fn setting(env: &Env, name: &str) -> Option<Item> {
+    env.get(Symbol::intern(name))
+}
+
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+/// The directory named by the XDG base directory variable `var`, or `default`
+/// in the home directory.
+fn xdg_dir(var: &str, default: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home().map(|home| home.join(default)))
+}
+
+/// The init file, if there is one.
+pub(crate) fn init_file() -> Option<PathBuf> {
+    let config = xdg_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join("skim/config"));
+    let skimrc = home().map(|home| home.join(".skimrc"));
+    config.into_iter().chain(skimrc).find(|file| file.is_file())
+}
+
+/// The file the REPL history is kept in.
+pub(crate) fn history_file() -> Option<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state").map(|dir| dir.join("skim/history"))
+}
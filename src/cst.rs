@@ -0,0 +1,308 @@
+//! A lossless concrete syntax tree of source code, for tools like the
+//! formatter that need the comments and the layout that `lex` throws away.
+//!
+//! The tree is built from the pieces of the lexer's `Scanner`, so it has the
+//! same tokens as `lex` gives the parser. Every token carries the trivia
+//! around it: whitespace, line comments, block comments `#| ... |#` and datum
+//! comments `#;`. A token's trailing trivia is what follows it up to the end
+//! of its line, all other trivia leads the next token. Writing the tree out
+//! gives back the exact source, and the `Item`s the parser reads from the
+//! source can be derived from it.
+use crate::ast::*;
+pub(crate) use crate::parser::Trivia;
+use crate::parser::{label, parse, Piece, Scanner, Token};
+use crate::Exception::*;
+use std::fmt::Display;
+
+/// A token of the parser with the trivia around it.
+pub(crate) struct CstToken {
+    pub leading: Vec<Trivia>,
+    pub token: Token,
+    pub trailing: Vec<Trivia>,
+}
+
+impl CstToken {
+    pub fn text(&self) -> &str {
+        self.token.text()
+    }
+}
+
+pub(crate) enum Node {
+    Atom(CstToken),
+    /// A list or a vector, by the tokens that open and close it.
+    List {
+        open: CstToken,
+        children: Vec<Node>,
+        close: CstToken,
+    },
+    /// A datum after a quote or a datum label like `#0=`.
+    Prefixed {
+        prefix: CstToken,
+        datum: Box<Node>,
+    },
+}
+
+pub(crate) struct Cst {
+    pub nodes: Vec<Node>,
+    /// The trivia after the last datum.
+    pub trivia: Vec<Trivia>,
+}
+
+/// Turns pieces into tokens with their trivia.
+struct Builder {
+    pieces: std::iter::Peekable<std::vec::IntoIter<Piece>>,
+}
+
+impl Builder {
+    /// The datum comment that starts with `marker`.
+    fn datum_comment(&mut self, marker: Token) -> Result<Trivia, Exn> {
+        let mut text = marker.text().to_string();
+        self.commented_datum(&mut text, marker.meta())?;
+        Ok(Trivia::DatumComment(text))
+    }
+
+    /// The text of the datum after a `#;`, with the trivia before it.
+    fn commented_datum(&mut self, text: &mut String, meta: &Meta) -> Result<(), Exn> {
+        let mut depth = 0;
+        loop {
+            let piece = match self.pieces.next() {
+                // a closing parenthesis right after it ends the list instead
+                Some(Piece::Token(token)) if depth == 0 && token.text() == ")" => None,
+                piece => piece,
+            };
+            let piece = match piece {
+                Some(piece) => piece,
+                None => return Err(Exn::syntax(meta.clone(), "Expected a datum after #;")),
+            };
+            match piece {
+                Piece::Trivia(trivia) => text.push_str(trivia.text()),
+                Piece::DatumComment(marker) => {
+                    text.push_str(marker.text());
+                    self.commented_datum(text, marker.meta())?;
+                }
+                Piece::Token(token) => {
+                    text.push_str(token.text());
+                    match token.text() {
+                        "(" | "#(" => depth += 1,
+                        ")" => depth -= 1,
+                        "'" => continue,
+                        prefix if label(prefix, '=').is_some() => continue,
+                        _ => {}
+                    }
+                    if depth <= 0 {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    /// The next token with its trivia, or the trivia at the end.
+    fn token(&mut self) -> Result<Result<CstToken, Vec<Trivia>>, Exn> {
+        let mut leading = Vec::new();
+        let token = loop {
+            match self.pieces.next() {
+                Some(Piece::Trivia(trivia)) => leading.push(trivia),
+                Some(Piece::DatumComment(marker)) => leading.push(self.datum_comment(marker)?),
+                Some(Piece::Token(token)) => break token,
+                None => return Ok(Err(leading)),
+            }
+        };
+        let mut token = CstToken {
+            leading,
+            token,
+            trailing: Vec::new(),
+        };
+        loop {
+            match self.pieces.peek_mut() {
+                Some(Piece::Trivia(Trivia::Whitespace(text))) => {
+                    if let Some(i) = text.find('\n') {
+                        // the line break leads the next token
+                        if i > 0 {
+                            token
+                                .trailing
+                                .push(Trivia::Whitespace(text[..i].to_string()));
+                            *text = text[i..].to_string();
+                        }
+                        break;
+                    }
+                }
+                Some(Piece::Trivia(_)) | Some(Piece::DatumComment(_)) => {}
+                Some(Piece::Token(_)) | None => break,
+            }
+            match self.pieces.next() {
+                Some(Piece::Trivia(trivia)) => token.trailing.push(trivia),
+                Some(Piece::DatumComment(marker)) => {
+                    token.trailing.push(self.datum_comment(marker)?)
+                }
+                _ => unreachable!(),
+            }
+        }
+        Ok(Ok(token))
+    }
+
+    fn node(&mut self, token: CstToken) -> Result<Node, Exn> {
+        let meta = token.token.meta().clone();
+        match token.text() {
+            "(" | "#(" => {
+                let mut children = Vec::new();
+                loop {
+                    match self.token()? {
+                        Ok(close) if close.text() == ")" => {
+                            return Ok(Node::List {
+                                open: token,
+                                children,
+                                close,
+                            })
+                        }
+                        Ok(next) => children.push(self.node(next)?),
+                        Err(_) => return Err(Exn::syntax(meta, "Expected \")\" for this list")),
+                    }
+                }
+            }
+            ")" => Err(Exn::syntax(meta, "Found unexpected \")\"")),
+            prefix if prefix == "'" || label(prefix, '=').is_some() => match self.token()? {
+                Ok(next) => Ok(Node::Prefixed {
+                    prefix: token,
+                    datum: Box::new(self.node(next)?),
+                }),
+                Err(_) => Err(Exn::syntax(meta, "Expected a datum")),
+            },
+            _ => Ok(Node::Atom(token)),
+        }
+    }
+}
+
+impl Cst {
+    /// Reads the source `code` of the file `file`.
+    pub fn parse(code: &str, file: Option<&str>) -> Result<Cst, Exn> {
+        let mut scanner = Scanner::new(code, file);
+        let mut pieces = Vec::new();
+        while let Some(piece) = scanner.next() {
+            pieces.push(piece);
+        }
+        if let Some(open) = scanner.unterminated {
+            return Err(Exn::syntax(
+                open.meta().clone(),
+                "Unterminated block comment",
+            ));
+        }
+        let mut builder = Builder {
+            pieces: pieces.into_iter().peekable(),
+        };
+        let mut nodes = Vec::new();
+        loop {
+            match builder.token()? {
+                Ok(token) => nodes.push(builder.node(token)?),
+                Err(trivia) => return Ok(Cst { nodes, trivia }),
+            }
+        }
+    }
+
+    /// The expressions the parser reads from the source.
+    pub fn items(&self) -> Result<Vec<Item>, Exn> {
+        let mut items = Vec::new();
+        for node in &self.nodes {
+            let mut tokens = Vec::new();
+            node.tokens(&mut |token| tokens.push(token.token.clone()));
+            items.push(parse(&tokens)?.0);
+        }
+        Ok(items)
+    }
+}
+
+impl Node {
+    /// Calls `visit` with the tokens of this node in order.
+    pub fn tokens(&self, visit: &mut dyn FnMut(&CstToken)) {
+        match self {
+            Node::Atom(token) => visit(token),
+            Node::List {
+                open,
+                children,
+                close,
+            } => {
+                visit(open);
+                for child in children {
+                    child.tokens(visit);
+                }
+                visit(close);
+            }
+            Node::Prefixed { prefix, datum } => {
+                visit(prefix);
+                datum.tokens(visit);
+            }
+        }
+    }
+}
+
+impl Display for Cst {
+    /// Writes the source the tree was read from.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut result = Ok(());
+        for node in &self.nodes {
+            node.tokens(&mut |token| {
+                for trivia in token.leading.iter() {
+                    result = result.and(f.write_str(trivia.text()));
+                }
+                result = result.and(f.write_str(token.text()));
+                for trivia in token.trailing.iter() {
+                    result = result.and(f.write_str(trivia.text()));
+                }
+            });
+        }
+        for trivia in &self.trivia {
+            result = result.and(f.write_str(trivia.text()));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::lex;
+
+    const SOURCE: &str = "; a line comment
+(define (f x) ; trailing
+  #| a block #| nested |# comment |#
+  (g x #;(h x) 'y)) #;#;skipped twice
+
+#0=(a . #0#)   \t
+#(1 \"two\" #\\)) #|
+|# end #;(last)
+";
+
+    fn ok<T>(result: Result<T, Exn>) -> T {
+        result.unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    #[test]
+    fn writes_back_the_source() {
+        let cst = ok(Cst::parse(SOURCE, None));
+        assert_eq!(cst.to_string(), SOURCE);
+        for source in ["", "  \n", "; only a comment", "#;x", "a"] {
+            assert_eq!(ok(Cst::parse(source, None)).to_string(), source);
+        }
+    }
+
+    #[test]
+    fn items_are_what_the_parser_reads() {
+        let items = ok(ok(Cst::parse(SOURCE, None)).items());
+        let tokens = lex(SOURCE, None);
+        let mut rest = &tokens[..];
+        let mut parsed = Vec::new();
+        while !rest.is_empty() {
+            let (item, next) = ok(parse(rest));
+            parsed.push(item);
+            rest = next;
+        }
+        assert_eq!(items.len(), parsed.len());
+        for (item, parsed) in items.iter().zip(&parsed) {
+            assert_eq!(item.to_string(), parsed.to_string());
+            assert_eq!(
+                (item.meta.line, item.meta.position),
+                (parsed.meta.line, parsed.meta.position)
+            );
+        }
+    }
+}
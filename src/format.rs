@@ -0,0 +1,295 @@
+//! The source code formatter behind `skim-lisp fmt`.
+//!
+//! Source is read into a tree that keeps the comments and where the blank
+//! lines were, and laid out again with the rules of the pretty printer:
+//! special forms like `define` indent their body, calls align their
+//! arguments and quoted lists are laid out as data. Formatting formatted
+//! source doesn't change it.
+use crate::cst::{self, Cst, CstToken, Trivia};
+use crate::pretty::{body, form, list, render, Doc, Form};
+use crate::run::read_file;
+use crate::Exception::*;
+use std::fs;
+
+/// The width formatted source is laid out to fit.
+const WIDTH: usize = 80;
+
+enum Kind {
+    Atom(String),
+    /// A line comment with its semicolons, or a block or datum comment.
+    Comment(String),
+    /// A list or vector, by what opens it.
+    List(&'static str, Vec<Node>),
+    /// A datum after something like a quote or a datum label.
+    Prefixed(String, Box<Node>),
+}
+
+struct Node {
+    kind: Kind,
+    /// Whether an empty line comes before it.
+    blank_before: bool,
+    /// Whether this is a comment on the line of the code before it.
+    trailing: bool,
+}
+
+/// Builds the formatter's tree from the syntax tree, turning the comments
+/// in the trivia into nodes of their own.
+#[derive(Default)]
+struct Builder {
+    /// The line breaks since the last node.
+    breaks: usize,
+}
+
+impl Builder {
+    /// Whether an empty line came since the last node, which starts a new one.
+    fn blank(&mut self) -> bool {
+        std::mem::take(&mut self.breaks) > 1
+    }
+
+    fn push(&mut self, nodes: &mut Vec<Node>, kind: Kind, trailing: bool) {
+        let blank_before = self.blank();
+        nodes.push(Node {
+            kind,
+            blank_before,
+            trailing,
+        });
+    }
+
+    fn trivia(&mut self, trivia: &[Trivia], nodes: &mut Vec<Node>, trailing: bool) {
+        for trivia in trivia {
+            match trivia {
+                Trivia::Whitespace(text) => self.breaks += text.matches('\n').count(),
+                // line comments keep no whitespace at their end
+                Trivia::LineComment(text) => {
+                    let kind = Kind::Comment(text.trim_end().to_string());
+                    self.push(nodes, kind, trailing)
+                }
+                Trivia::BlockComment(text) | Trivia::DatumComment(text) => {
+                    self.push(nodes, Kind::Comment(text.clone()), trailing)
+                }
+            }
+        }
+    }
+
+    /// Adds `node` to `nodes`, with the comments before and after it.
+    fn node(&mut self, node: &cst::Node, nodes: &mut Vec<Node>) {
+        let (first, last) = match node {
+            cst::Node::Atom(token) => (token, token),
+            cst::Node::List { open, close, .. } => (open, close),
+            cst::Node::Prefixed { prefix, datum } => (prefix, last_token(datum)),
+        };
+        self.trivia(&first.leading, nodes, false);
+        let blank_before = self.blank();
+        let kind = match node {
+            cst::Node::Atom(token) => Kind::Atom(token.text().to_string()),
+            cst::Node::List {
+                open,
+                children,
+                close,
+            } => {
+                let mut inner = Vec::new();
+                self.trivia(&open.trailing, &mut inner, true);
+                for child in children {
+                    self.node(child, &mut inner);
+                }
+                self.trivia(&close.leading, &mut inner, false);
+                // the lines before the parenthesis are not between nodes
+                self.blank();
+                let open = if open.text() == "#(" { "#(" } else { "(" };
+                Kind::List(open, inner)
+            }
+            cst::Node::Prefixed { prefix, datum } => {
+                // a datum can't be separated from its prefix
+                let mut inner = Vec::new();
+                self.trivia(&prefix.trailing, &mut inner, true);
+                self.node(datum, &mut inner);
+                let datum = inner.pop().unwrap();
+                nodes.append(&mut inner);
+                Kind::Prefixed(prefix.text().to_string(), Box::new(datum))
+            }
+        };
+        nodes.push(Node {
+            kind,
+            blank_before,
+            trailing: false,
+        });
+        self.trivia(&last.trailing, nodes, true);
+    }
+}
+
+fn last_token(node: &cst::Node) -> &CstToken {
+    match node {
+        cst::Node::Atom(token) => token,
+        cst::Node::List { close, .. } => close,
+        cst::Node::Prefixed { datum, .. } => last_token(datum),
+    }
+}
+
+/// Returns true if `atom` is a symbol rather than a constant.
+fn is_symbol(atom: &str) -> bool {
+    !atom.starts_with('"') && !atom.starts_with('#') && atom.parse::<f64>().is_err()
+}
+
+/// Returns true if `node` is a line comment, which ends its line.
+fn is_line_comment(node: &Node) -> bool {
+    matches!(&node.kind, Kind::Comment(comment) if comment.starts_with(';'))
+}
+
+fn is_comment(node: &Node) -> bool {
+    matches!(node.kind, Kind::Comment(_))
+}
+
+/// Returns true if `node` is a comment on a line of its own, which is kept
+/// there.
+fn is_own_line_comment(node: &Node) -> bool {
+    is_comment(node) && !node.trailing
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Layout {
+    Code,
+    /// Quoted data, which is not laid out like calls and special forms.
+    Data,
+    /// The clauses of a `cond`, which are laid out as data while what is in
+    /// them is code again.
+    Clauses,
+}
+
+/// The documents of `nodes`, with comments at the end of a line attached to
+/// what comes before them.
+fn docs(nodes: &[Node], layout: Layout) -> Vec<Doc> {
+    let mut docs: Vec<Doc> = Vec::new();
+    for node in nodes {
+        if node.blank_before {
+            if let Some(last) = docs.pop() {
+                docs.push(Doc::Concat(vec![last, Doc::Break, Doc::BlankLine]));
+            }
+        }
+        match &node.kind {
+            Kind::Comment(comment) if node.trailing && !docs.is_empty() => {
+                let last = docs.pop().unwrap();
+                let text = Doc::Text(format!(" {}", comment));
+                docs.push(match is_line_comment(node) {
+                    true => Doc::Concat(vec![last, text, Doc::Break]),
+                    false => Doc::Concat(vec![last, text]),
+                });
+            }
+            Kind::Comment(_) if !docs.is_empty() => {
+                let last = docs.pop().unwrap();
+                docs.push(Doc::Concat(vec![last, Doc::Break]));
+                docs.push(doc(node, layout));
+            }
+            _ => docs.push(doc(node, layout)),
+        }
+    }
+    docs
+}
+
+fn doc(node: &Node, layout: Layout) -> Doc {
+    match &node.kind {
+        Kind::Atom(atom) => Doc::text(atom),
+        Kind::Comment(comment) if is_line_comment(node) => {
+            Doc::Concat(vec![Doc::text(comment), Doc::Break])
+        }
+        Kind::Comment(comment) => Doc::text(comment),
+        Kind::Prefixed(prefix, node) if prefix == "'" => {
+            Doc::Concat(vec![Doc::text(prefix), doc(node, Layout::Data)])
+        }
+        Kind::Prefixed(prefix, node) => Doc::Concat(vec![Doc::text(prefix), doc(node, layout)]),
+        Kind::List(open, nodes) => {
+            let inner = match layout {
+                Layout::Code if *open == "(" => match nodes.first() {
+                    Some(Node {
+                        kind: Kind::Atom(head),
+                        ..
+                    }) if is_symbol(head) => return call(head, nodes),
+                    _ => Layout::Code,
+                },
+                Layout::Clauses => Layout::Code,
+                _ => Layout::Data,
+            };
+            let mut items = docs(nodes, inner);
+            if nodes.first().is_some_and(is_own_line_comment) {
+                // an empty first item puts the comment on the next line
+                items.insert(0, Doc::text(""));
+            }
+            list(open, end(items, nodes), ")")
+        }
+    }
+}
+
+/// Lays out the call or special form `(head args ...)`.
+fn call(head: &str, nodes: &[Node]) -> Doc {
+    let args = &nodes[1..];
+    if args.is_empty() {
+        return Doc::Text(format!("({})", head));
+    }
+    if is_own_line_comment(&args[0]) {
+        // the comment can't go on the line of the head
+        return list("(", end(docs(nodes, Layout::Code), nodes), ")");
+    }
+    let symbol_operand = match &args[0].kind {
+        Kind::Atom(atom) => is_symbol(atom),
+        _ => false,
+    };
+    let layout = match form(head, symbol_operand) {
+        // the operands go on the line of the keyword, so only the last one
+        // can have a comment after it
+        Some(Form::Body(n)) if args.len() > n && !args[..n].iter().any(is_comment) => {
+            let split = if args[n].trailing { n + 1 } else { n };
+            let operands = docs(&args[..split], Layout::Code);
+            let rest = docs(&args[split..], Layout::Code);
+            return body(head, operands, end(rest, args));
+        }
+        Some(Form::Clauses) => Layout::Clauses,
+        _ => Layout::Code,
+    };
+    list(&format!("({} ", head), end(docs(args, layout), args), ")")
+}
+
+/// Adds an empty document after a comment at the end of `nodes`, so that the
+/// closing parenthesis goes on a line of its own.
+fn end(mut docs: Vec<Doc>, nodes: &[Node]) -> Vec<Doc> {
+    if nodes.last().is_some_and(is_line_comment) {
+        docs.push(Doc::text(""));
+    }
+    docs
+}
+
+/// Formats the source `code` of the file `file_name`.
+pub fn format_source(code: &str, file_name: Option<&str>) -> Result<String, Exn> {
+    let cst = Cst::parse(code, file_name)?;
+    // source the reader rejects, like an undefined datum label, is left alone
+    cst.items()?;
+    let mut builder = Builder::default();
+    let mut nodes = Vec::new();
+    for node in &cst.nodes {
+        builder.node(node, &mut nodes);
+    }
+    builder.trivia(&cst.trivia, &mut nodes, false);
+    let mut formatted = String::new();
+    for doc in docs(&nodes, Layout::Code) {
+        formatted.push_str(&render(&doc, WIDTH, 0));
+        formatted.push('\n');
+    }
+    Ok(formatted)
+}
+
+/// Formats the file `file_name` in place, or only checks whether it is
+/// formatted if `check` is true. Returns false if it was not formatted.
+pub fn format_file(file_name: &str, check: bool) -> Result<bool, Exn> {
+    let code = read_file(&Meta::empty(), file_name)?;
+    let formatted = format_source(&code, Some(file_name))?;
+    if formatted == code {
+        return Ok(true);
+    }
+    if !check {
+        fs::write(file_name, formatted).map_err(|e| {
+            Exn::io(
+                Meta::empty(),
+                &format!("could not write {}: {}", file_name, e),
+            )
+        })?;
+    }
+    Ok(false)
+}
@@ -0,0 +1,327 @@
+//! Cycle collecting garbage collector for skim heap objects.
+//!
+//! Heap objects are reference counted, which frees everything that is not part
+//! of a cycle right away. Every object is also registered with the heap so that
+//! `collect` can find cycles: it subtracts all references that heap objects hold
+//! to each other from the reference counts, keeps everything that is still
+//! referenced from the outside (the rust stack, the REPL, ...) alive together
+//! with what it reaches, and clears the rest. Because outside references are
+//! detected from the counts, a collection is safe at any point of evaluation.
+use crate::Exception::*;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::ops::Deref;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+/// Heap objects report the `Gc` references they hold, so the collector can find
+/// cycles between them.
+pub(crate) trait Trace {
+    /// Calls `visit` with the address of every `Gc` this object references
+    /// directly. Returns `false` if the object is borrowed and can't be traced
+    /// right now, in which case the collector treats it as alive.
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool;
+
+    /// Drops all references held by this object to break the cycle it is part of.
+    fn clear(&self) {}
+
+    /// Bytes owned by this object outside of its own allocation.
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+struct GcBox<T> {
+    size: usize,
+    value: T,
+}
+
+impl<T: Trace> Trace for GcBox<T> {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        self.value.trace(visit)
+    }
+
+    fn clear(&self) {
+        self.value.clear()
+    }
+}
+
+impl<T> Drop for GcBox<T> {
+    fn drop(&mut self) {
+        let size = self.size;
+        let _ = HEAP.try_with(|heap| {
+            heap.bytes.set(heap.bytes.get() - size);
+            heap.objects.set(heap.objects.get() - 1);
+        });
+    }
+}
+
+/// A shared pointer to a heap object.
+pub(crate) struct Gc<T: Trace + 'static>(Rc<GcBox<T>>);
+
+impl<T: Trace + 'static> Gc<T> {
+    pub fn new(value: T) -> Gc<T> {
+        let size = size_of::<GcBox<T>>() + value.heap_size();
+        let rc = Rc::new(GcBox { size, value });
+        let weak: Weak<dyn Trace> = Rc::downgrade(&rc) as Weak<GcBox<T>>;
+        HEAP.with(|heap| {
+            let mut registry = heap.registry.borrow_mut();
+            if registry.len() >= heap.prune_at.get() {
+                // objects freed by reference counting leave dead entries behind
+                registry.retain(|obj| obj.strong_count() > 0);
+                heap.prune_at.set((registry.len() * 2).max(MIN_PRUNE_AT));
+            }
+            registry.push(weak);
+            heap.bytes.set(heap.bytes.get() + size);
+            heap.objects.set(heap.objects.get() + 1);
+            heap.allocations.set(heap.allocations.get() + 1);
+            heap.allocated.set(heap.allocated.get() + size);
+        });
+        Gc(rc)
+    }
+
+    /// Returns true if this is the only reference to the object.
+    pub fn is_unique(&self) -> bool {
+        Rc::strong_count(&self.0) == 1
+    }
+
+    pub fn ptr_eq(a: &Gc<T>, b: &Gc<T>) -> bool {
+        Rc::ptr_eq(&a.0, &b.0)
+    }
+
+    /// The address identifying this object, as passed to `Trace::trace` visitors.
+    pub fn addr(&self) -> usize {
+        Rc::as_ptr(&self.0) as *const () as usize
+    }
+}
+
+impl<T: Trace + 'static> Clone for Gc<T> {
+    fn clone(&self) -> Self {
+        Gc(self.0.clone())
+    }
+}
+
+impl<T: Trace + 'static> Deref for Gc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0.value
+    }
+}
+
+/// Heap size below which no automatic collection happens.
+const MIN_THRESHOLD: usize = 1 << 20;
+const MIN_PRUNE_AT: usize = 1024;
+
+struct Heap {
+    registry: RefCell<Vec<Weak<dyn Trace>>>,
+    prune_at: Cell<usize>,
+    bytes: Cell<usize>,
+    objects: Cell<usize>,
+    // totals over the life of the heap
+    allocations: Cell<usize>,
+    allocated: Cell<usize>,
+    threshold: Cell<usize>,
+    limit: Cell<Option<usize>>,
+    collections: Cell<usize>,
+    total_pause: Cell<Duration>,
+    last_pause: Cell<Duration>,
+    last_freed: Cell<usize>,
+}
+
+thread_local! {
+    static HEAP: Heap = Heap {
+        registry: RefCell::new(Vec::new()),
+        prune_at: Cell::new(MIN_PRUNE_AT),
+        bytes: Cell::new(0),
+        objects: Cell::new(0),
+        allocations: Cell::new(0),
+        allocated: Cell::new(0),
+        threshold: Cell::new(MIN_THRESHOLD),
+        limit: Cell::new(None),
+        collections: Cell::new(0),
+        total_pause: Cell::new(Duration::from_secs(0)),
+        last_pause: Cell::new(Duration::from_secs(0)),
+        last_freed: Cell::new(0),
+    };
+}
+
+pub struct Stats {
+    pub bytes: usize,
+    pub objects: usize,
+    /// The number of objects allocated so far, including those freed since.
+    pub allocations: usize,
+    /// The bytes allocated so far, including those freed since.
+    pub allocated: usize,
+    pub limit: Option<usize>,
+    pub collections: usize,
+    pub total_pause: Duration,
+    pub last_pause: Duration,
+    pub last_freed: usize,
+}
+
+pub fn stats() -> Stats {
+    HEAP.with(|heap| Stats {
+        bytes: heap.bytes.get(),
+        objects: heap.objects.get(),
+        allocations: heap.allocations.get(),
+        allocated: heap.allocated.get(),
+        limit: heap.limit.get(),
+        collections: heap.collections.get(),
+        total_pause: heap.total_pause.get(),
+        last_pause: heap.last_pause.get(),
+        last_freed: heap.last_freed.get(),
+    })
+}
+
+/// Limits the heap to `limit` bytes. Evaluation fails with an out of memory
+/// condition once the heap is still larger than that after a collection.
+pub fn set_heap_limit(limit: Option<usize>) {
+    HEAP.with(|heap| {
+        heap.limit.set(limit);
+        update_threshold(heap);
+    });
+}
+
+fn update_threshold(heap: &Heap) {
+    let threshold = (heap.bytes.get() * 2).max(MIN_THRESHOLD);
+    heap.threshold.set(match heap.limit.get() {
+        Some(limit) => threshold.min(limit),
+        None => threshold,
+    });
+}
+
+/// Collects garbage if the heap has grown enough since the last collection and
+/// enforces the heap limit. Call this only where an error can be returned.
+pub(crate) fn safepoint(meta: &Meta) -> Result<(), Exn> {
+    let over_threshold = HEAP.with(|heap| heap.bytes.get() > heap.threshold.get());
+    if !over_threshold {
+        return Ok(());
+    }
+    collect();
+    HEAP.with(|heap| match heap.limit.get() {
+        Some(limit) if heap.bytes.get() > limit => {
+            Err(Exn::new(meta.clone(), Condition::OutOfMemory(Some(limit))))
+        }
+        _ => Ok(()),
+    })
+}
+
+/// Fails with an out of memory condition if allocating another `bytes` would
+/// take the heap beyond its limit, even after a collection. Primitives that
+/// build large objects in one go call this first, since they would not reach
+/// a safepoint before running out of memory.
+pub(crate) fn reserve(meta: &Meta, bytes: usize) -> Result<(), Exn> {
+    let fits = |heap: &Heap| match heap.limit.get() {
+        Some(limit) => heap.bytes.get().saturating_add(bytes) <= limit,
+        None => true,
+    };
+    if HEAP.with(fits) {
+        return Ok(());
+    }
+    collect();
+    HEAP.with(|heap| match heap.limit.get() {
+        Some(limit) if !fits(heap) => {
+            Err(Exn::new(meta.clone(), Condition::OutOfMemory(Some(limit))))
+        }
+        _ => Ok(()),
+    })
+}
+
+/// Runs a full collection and returns the number of objects freed.
+pub fn collect() -> usize {
+    let start = Instant::now();
+    let registry = HEAP.with(|heap| heap.registry.replace(Vec::new()));
+    let objects: Vec<Rc<dyn Trace>> = registry.iter().filter_map(Weak::upgrade).collect();
+    drop(registry);
+    let index: HashMap<usize, usize> = objects
+        .iter()
+        .enumerate()
+        .map(|(i, obj)| (Rc::as_ptr(obj) as *const () as usize, i))
+        .collect();
+
+    // count the references from outside of the heap, the upgrade above holds one
+    let mut outside: Vec<isize> = objects
+        .iter()
+        .map(|obj| Rc::strong_count(obj) as isize - 1)
+        .collect();
+    let mut marked = vec![false; objects.len()];
+    for (i, obj) in objects.iter().enumerate() {
+        let traced = obj.trace(&mut |child| {
+            if let Some(&j) = index.get(&child) {
+                outside[j] -= 1;
+            }
+        });
+        if !traced {
+            marked[i] = true;
+        }
+    }
+
+    // everything reachable from an outside reference is alive
+    let mut stack: Vec<usize> = (0..objects.len())
+        .filter(|&i| marked[i] || outside[i] > 0)
+        .collect();
+    for &i in &stack {
+        marked[i] = true;
+    }
+    while let Some(i) = stack.pop() {
+        objects[i].trace(&mut |child| {
+            if let Some(&j) = index.get(&child) {
+                if !marked[j] {
+                    marked[j] = true;
+                    stack.push(j);
+                }
+            }
+        });
+    }
+
+    let mut freed = 0;
+    let mut survivors = Vec::new();
+    for (obj, alive) in objects.iter().zip(marked) {
+        if alive {
+            survivors.push(Rc::downgrade(obj));
+        } else {
+            obj.clear();
+            freed += 1;
+        }
+    }
+    drop(objects);
+
+    HEAP.with(|heap| {
+        let mut registry = heap.registry.borrow_mut();
+        survivors.append(&mut registry);
+        *registry = survivors;
+        heap.prune_at.set((registry.len() * 2).max(MIN_PRUNE_AT));
+        let pause = start.elapsed();
+        heap.collections.set(heap.collections.get() + 1);
+        heap.last_pause.set(pause);
+        heap.total_pause.set(heap.total_pause.get() + pause);
+        heap.last_freed.set(freed);
+        update_threshold(heap);
+    });
+    freed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::exec;
+    use crate::runtime::default_env;
+
+    #[test]
+    fn collects_dropped_cycles() {
+        collect();
+        let before = stats().objects;
+        let mut env = default_env();
+        let code = "(define pair (list 1 2))
+                    (set-cdr! (cdr pair) pair)
+                    (define (f) f)";
+        assert!(exec(&mut env, code, None).is_ok());
+        // the environment holds the closure that holds the environment
+        drop(env);
+        assert!(stats().objects > before);
+        assert!(collect() > 0);
+        assert_eq!(stats().objects, before);
+    }
+}
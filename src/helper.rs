@@ -0,0 +1,259 @@
+//! Line editing support for the REPL: completion of bound names, syntax
+//! highlighting, hints with the parameters of the procedure being called,
+//! and validation so that incomplete expressions are continued on the next
+//! line instead of being submitted.
+use crate::ast::*;
+use crate::commands;
+use crate::config::Theme;
+use crate::parser::{is_incomplete, lex};
+use crate::primitives::SYNTAX;
+use crate::runtime::*;
+use crate::symbol::Symbol;
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Helper};
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// Special forms that are not in `SYNTAX`, because `null-environment` does
+/// not bind them.
+const KEYWORDS: [&str; 2] = ["import", "define-library"];
+
+pub(crate) struct ReplHelper {
+    env: Env,
+    theme: Theme,
+}
+
+impl ReplHelper {
+    pub fn new(env: Env, theme: Theme) -> ReplHelper {
+        ReplHelper { env, theme }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Open,
+    Close,
+    Quote,
+    String,
+    Comment,
+    Constant,
+    Symbol,
+}
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || "()'\";".contains(c)
+}
+
+/// Splits `line` into the byte ranges of its tokens. Unlike `lex`, this keeps
+/// comments and copes with whatever has been typed so far.
+fn scan(line: &str) -> Vec<(Kind, Range<usize>)> {
+    let mut spans = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let kind = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Kind::Open,
+            ')' => Kind::Close,
+            '\'' => Kind::Quote,
+            ';' => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+                Kind::Comment
+            }
+            '#' if line[start..].starts_with("#|") => {
+                chars.next();
+                let mut depth = 1;
+                while depth > 0 {
+                    match chars.next() {
+                        Some((i, '#')) if line[i..].starts_with("#|") => {
+                            chars.next();
+                            depth += 1;
+                        }
+                        Some((i, '|')) if line[i..].starts_with("|#") => {
+                            chars.next();
+                            depth -= 1;
+                        }
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+                Kind::Comment
+            }
+            '"' => {
+                let mut escaped = false;
+                for (_, c) in chars.by_ref() {
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+                Kind::String
+            }
+            _ => {
+                // the character of a character literal may be a delimiter
+                if c == '#' && line[start..].starts_with("#\\") {
+                    chars.next();
+                    chars.next();
+                }
+                while chars.next_if(|&(_, c)| !is_delimiter(c)).is_some() {}
+                let end = chars.peek().map_or(line.len(), |&(i, _)| i);
+                let atom = &line[start..end];
+                if atom.starts_with('#') || atom.parse::<f64>().is_ok() {
+                    Kind::Constant
+                } else {
+                    Kind::Symbol
+                }
+            }
+        };
+        let end = chars.peek().map_or(line.len(), |&(i, _)| i);
+        spans.push((kind, start..end));
+    }
+    spans
+}
+
+/// Returns the index in `spans` of the parenthesis matching the one at
+/// `index`.
+fn matching_paren(spans: &[(Kind, Range<usize>)], index: usize) -> Option<usize> {
+    let mut depth = 0;
+    let step = |i: usize| -> Option<usize> {
+        match spans[index].0 {
+            Kind::Open => Some(i + 1).filter(|&i| i < spans.len()),
+            _ => i.checked_sub(1),
+        }
+    };
+    let mut i = index;
+    loop {
+        match spans[i].0 {
+            Kind::Open | Kind::Close if spans[i].0 == spans[index].0 => depth += 1,
+            Kind::Open | Kind::Close => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return Some(i);
+        }
+        i = step(i)?;
+    }
+}
+
+pub(crate) fn is_keyword(name: &str) -> bool {
+    SYNTAX.iter().any(|(keyword, _)| *keyword == name) || KEYWORDS.contains(&name)
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos].rfind(is_delimiter).map_or(0, |i| i + 1);
+        let prefix = &line[start..pos];
+        if start == 0 && commands::is_command(prefix) {
+            let names = commands::names().filter(|name| name.starts_with(prefix));
+            return Ok((start, names.collect()));
+        }
+        let names = self
+            .env
+            .names()
+            .into_iter()
+            .map(|name| name.name().to_string())
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        Ok((start, names))
+    }
+}
+
+impl Hinter for ReplHelper {
+    /// Shows the parameters of the procedure whose call the cursor is in.
+    fn hint(&self, line: &str, pos: usize, _: &Context<'_>) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+        let spans = scan(line);
+        let mut open = Vec::new();
+        for (i, (kind, _)) in spans.iter().enumerate() {
+            match kind {
+                Kind::Open => open.push(i),
+                Kind::Close => {
+                    open.pop();
+                }
+                _ => {}
+            }
+        }
+        let (kind, range) = spans.get(open.pop()? + 1)?;
+        if *kind != Kind::Symbol {
+            return None;
+        }
+        let name = &line[range.clone()];
+        match self.env.get(Symbol::intern(name))?.exp {
+            Exp::Lambda(lambda) => {
+                let signatures: Vec<String> = lambda
+                    .clauses
+                    .iter()
+                    .map(|clause| clause.params.signature(name))
+                    .collect();
+                Some(format!("  ; {}", signatures.join(" ")))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        let theme = &self.theme;
+        let spans = scan(line);
+        // the parenthesis at or right before the cursor and its match
+        let at_cursor = spans.iter().rposition(|(kind, range)| {
+            matches!(kind, Kind::Open | Kind::Close) && (range.start == pos || range.end == pos)
+        });
+        let matched: Vec<usize> = at_cursor
+            .and_then(|i| Some(vec![i, matching_paren(&spans, i)?]))
+            .unwrap_or_default();
+        let mut highlighted = String::with_capacity(line.len());
+        let mut last = 0;
+        for (i, (kind, range)) in spans.iter().enumerate() {
+            let text = &line[range.clone()];
+            let color = match kind {
+                _ if matched.contains(&i) => theme.paren,
+                Kind::String => theme.string,
+                Kind::Constant => theme.number,
+                Kind::Comment => theme.comment,
+                Kind::Symbol if is_keyword(text) => theme.keyword,
+                _ => "",
+            };
+            highlighted.push_str(&line[last..range.start]);
+            highlighted.push_str(&theme.paint(color, text));
+            last = range.end;
+        }
+        highlighted.push_str(&line[last..]);
+        Cow::Owned(highlighted)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(self.theme.paint(self.theme.hint, hint))
+    }
+
+    fn highlight_char(&self, _: &str, _: usize) -> bool {
+        // matching parentheses change with every cursor movement
+        true
+    }
+}
+
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if is_incomplete(&lex(ctx.input(), None)) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Helper for ReplHelper {}
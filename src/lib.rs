@@ -1,8 +1,24 @@
+pub mod format;
+pub mod gc;
+pub mod library;
 pub mod repl;
 pub mod run;
+pub mod sandbox;
 
-pub(crate) mod Exception;
+#[allow(non_snake_case)]
+pub mod Exception;
 pub(crate) mod ast;
+pub(crate) mod commands;
+pub(crate) mod config;
+pub(crate) mod cst;
+pub(crate) mod helper;
 pub(crate) mod parser;
+pub(crate) mod port;
+pub(crate) mod pretty;
 pub(crate) mod primitives;
+pub(crate) mod record;
 pub(crate) mod runtime;
+pub(crate) mod symbol;
+pub(crate) mod table;
+pub(crate) mod trace;
+pub(crate) mod write;
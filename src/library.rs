@@ -0,0 +1,357 @@
+//! Libraries (R7RS 5.6).
+//!
+//! Libraries are looked up by name: the built-in ones group the primitives,
+//! everything else is defined with `define-library`, either inline or in a file
+//! found on the search path. `(import (foo bar))` loads `foo/bar.sld` from the
+//! first directory of the search path that has it, so each library is only
+//! loaded once.
+use crate::ast::*;
+use crate::primitives::*;
+use crate::run::exec_file;
+use crate::runtime::*;
+use crate::symbol::Symbol;
+use crate::Exception::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// The environment variable holding additional library directories.
+pub const SEARCH_PATH_VAR: &str = "SKIM_LIBRARY_PATH";
+
+/// File extensions of library files, in the order they are tried.
+const EXTENSIONS: [&str; 2] = ["sld", "skim"];
+
+/// The bindings a library exports, by their external names.
+pub(crate) struct Library {
+    pub exports: Vec<(Symbol, Item)>,
+}
+
+struct Libraries {
+    search_path: RefCell<Vec<PathBuf>>,
+    loaded: RefCell<HashMap<String, Rc<Library>>>,
+    // names of the library files that are being loaded right now
+    loading: RefCell<Vec<String>>,
+}
+
+thread_local! {
+    static LIBRARIES: Libraries = Libraries {
+        search_path: RefCell::new(Vec::new()),
+        loaded: RefCell::new(HashMap::new()),
+        loading: RefCell::new(Vec::new()),
+    };
+}
+
+/// Adds a directory to search for library files. Directories are searched in
+/// the order they were added, followed by those in `SKIM_LIBRARY_PATH` and
+/// finally the current directory.
+pub fn add_search_path(dir: impl Into<PathBuf>) {
+    LIBRARIES.with(|libs| libs.search_path.borrow_mut().push(dir.into()));
+}
+
+fn search_path() -> Vec<PathBuf> {
+    let mut dirs = LIBRARIES.with(|libs| libs.search_path.borrow().clone());
+    if let Some(var) = env::var_os(SEARCH_PATH_VAR) {
+        dirs.extend(env::split_paths(&var));
+    }
+    dirs.push(PathBuf::from("."));
+    dirs
+}
+
+/// Returns the parts of a library name like `(srfi 1)`, which consists of
+/// symbols and non-negative integers.
+pub(crate) fn name_parts(name: &Item) -> Result<Vec<String>, Exn> {
+    let cell = match &name.exp {
+        Exp::Pair(cell) if name.exp.is_list() => cell.clone(),
+        x => return Err(Exn::typ(name.meta.clone(), "library name", &x.type_name())),
+    };
+    let mut parts = Vec::new();
+    for part in cell {
+        match part.exp {
+            Exp::Symbol(s) => parts.push(s.name().to_string()),
+            Exp::Number(n) if n >= 0.0 && n.fract() == 0.0 => parts.push(n.to_string()),
+            ref x => {
+                return Err(Exn::typ(
+                    part.meta.clone(),
+                    "symbol or integer",
+                    &x.type_name(),
+                ))
+            }
+        }
+    }
+    Ok(parts)
+}
+
+fn key(parts: &[String]) -> String {
+    format!("({})", parts.join(" "))
+}
+
+/// Makes the library `name` available to `import`, replacing an earlier
+/// definition of it.
+pub(crate) fn register(name: &[String], library: Library) {
+    LIBRARIES.with(|libs| {
+        libs.loaded.borrow_mut().insert(key(name), Rc::new(library));
+    });
+}
+
+/// Returns the library named by `name`, loading it from the search path if it
+/// hasn't been loaded yet.
+pub(crate) fn find(name: &Item) -> Result<Rc<Library>, Exn> {
+    let parts = name_parts(name)?;
+    let key = key(&parts);
+    if let Some(library) = LIBRARIES.with(|libs| libs.loaded.borrow().get(&key).cloned()) {
+        return Ok(library);
+    }
+    if let Some(prims) = builtin(&key) {
+        let exports = prims
+            .iter()
+            .map(|(name, fun)| (Symbol::intern(name), Item::prim(Meta::empty(), *fun)))
+            .collect();
+        register(&parts, Library { exports });
+        return find(name);
+    }
+    let cycle = LIBRARIES.with(|libs| {
+        let loading = libs.loading.borrow();
+        loading.iter().position(|l| *l == key).map(|start| {
+            let mut cycle = loading[start..].to_vec();
+            cycle.push(key.clone());
+            cycle.join(" -> ")
+        })
+    });
+    if let Some(cycle) = cycle {
+        return Err(Exn::other(
+            name.meta.clone(),
+            &format!("cyclic library imports: {}", cycle),
+        ));
+    }
+
+    let path = search_path()
+        .into_iter()
+        .flat_map(|dir| {
+            let file = parts.iter().fold(dir, |path, part| path.join(part));
+            EXTENSIONS.iter().map(move |ext| {
+                let mut path = file.clone().into_os_string();
+                path.push(".");
+                path.push(ext);
+                PathBuf::from(path)
+            })
+        })
+        .find(|path| path.is_file());
+    let path = match path {
+        Some(path) => path,
+        None => {
+            return Err(Exn::other(
+                name.meta.clone(),
+                &format!("library not found: {}", key),
+            ))
+        }
+    };
+    LIBRARIES.with(|libs| libs.loading.borrow_mut().push(key.clone()));
+    let res = load(name, &path);
+    LIBRARIES.with(|libs| libs.loading.borrow_mut().pop());
+    res?;
+    match LIBRARIES.with(|libs| libs.loaded.borrow().get(&key).cloned()) {
+        Some(library) => Ok(library),
+        None => Err(Exn::other(
+            name.meta.clone(),
+            &format!("{} does not define the library {}", path.display(), key),
+        )),
+    }
+}
+
+/// Evaluates the library file at `path`, which may only contain `define-library` forms.
+fn load(name: &Item, path: &Path) -> Result<(), Exn> {
+    let mut env = Env::new(None);
+    env.set_prim("define-library", prim_define_library);
+    exec_file(&mut env, &name.meta, &path.to_string_lossy())
+}
+
+/// Returns the primitives of the built-in library `name`.
+fn builtin(name: &str) -> Option<&'static [(&'static str, Primitive)]> {
+    BUILTIN
+        .iter()
+        .find(|(lib, _)| *lib == name)
+        .map(|(_, prims)| *prims)
+}
+
+/// The built-in libraries. Programs see all of them without an `import`.
+pub(crate) const BUILTIN: &[(&str, &[(&str, Primitive)])] = &[
+    ("(scheme base)", SCHEME_BASE),
+    ("(scheme write)", SCHEME_WRITE),
+    ("(scheme file)", SCHEME_FILE),
+    ("(scheme load)", SCHEME_LOAD),
+    ("(scheme read)", SCHEME_READ),
+    ("(scheme eval)", SCHEME_EVAL),
+    ("(scheme repl)", SCHEME_REPL),
+    ("(scheme r5rs)", SCHEME_R5RS),
+    ("(srfi 1)", SRFI_1),
+    ("(srfi 69)", SRFI_69),
+    ("(skim base)", SKIM_BASE),
+    ("(skim gc)", SKIM_GC),
+    ("(skim environment)", SKIM_ENVIRONMENT),
+];
+
+const SCHEME_BASE: &[(&str, Primitive)] = &[
+    ("define", prim_define),
+    ("lambda", prim_lambda),
+    ("case-lambda", prim_case_lambda),
+    ("define-record-type", prim_define_record_type),
+    ("apply", prim_apply),
+    ("if", prim_if),
+    ("cond", prim_cond),
+    ("begin", prim_begin),
+    ("include", prim_include),
+    ("or", prim_or),
+    ("and", prim_and),
+    ("quote", prim_quote),
+    ("+", prim_plus),
+    ("-", prim_minus),
+    ("car", prim_car),
+    ("cdr", prim_cdr),
+    ("cons", prim_cons),
+    ("set-car!", prim_set_car),
+    ("set-cdr!", prim_set_cdr),
+    ("list", prim_list),
+    ("length", prim_length),
+    ("append", prim_append),
+    ("reverse", prim_reverse),
+    ("list-tail", prim_list_tail),
+    ("list-ref", prim_list_ref),
+    ("list-copy", prim_list_copy),
+    ("map", prim_map),
+    ("for-each", prim_for_each),
+    ("eq?", prim_eq),
+    ("eqv?", prim_eqv),
+    ("equal?", prim_equal),
+    ("memq", prim_memq),
+    ("memv", prim_memv),
+    ("member", prim_member),
+    ("assq", prim_assq),
+    ("assv", prim_assv),
+    ("assoc", prim_assoc),
+    ("number?", prim_is_number),
+    ("pair?", prim_is_pair),
+    ("null?", prim_is_null),
+    ("list?", prim_is_list),
+    ("symbol?", prim_is_symbol),
+    ("string?", prim_is_string),
+    ("boolean?", prim_is_boolean),
+    ("procedure?", prim_is_procedure),
+    ("vector?", prim_is_vector),
+    ("char?", prim_is_char),
+    ("string->number", prim_string_to_number),
+    ("number->string", prim_number_to_string),
+    ("list->string", prim_list_to_string),
+    ("string->list", prim_string_to_list),
+    ("exact->inexact", prim_exact_to_inexact),
+    ("symbol->string", prim_symbol_to_string),
+    ("string->symbol", prim_string_to_symbol),
+    ("symbol=?", prim_symbol_eq),
+    ("string=?", prim_string_eq),
+    ("current-input-port", prim_current_input_port),
+    ("current-output-port", prim_current_output_port),
+    ("current-error-port", prim_current_error_port),
+    ("open-input-string", prim_open_input_string),
+    ("open-output-string", prim_open_output_string),
+    ("get-output-string", prim_get_output_string),
+    ("read-char", prim_read_char),
+    ("peek-char", prim_peek_char),
+    ("read-line", prim_read_line),
+    ("read-string", prim_read_string),
+    ("write-char", prim_write_char),
+    ("write-string", prim_write_string),
+    ("newline", prim_newline),
+    ("close-port", prim_close_port),
+    ("close-input-port", prim_close_port),
+    ("close-output-port", prim_close_port),
+    ("eof-object", prim_eof_object),
+    ("eof-object?", prim_is_eof_object),
+];
+
+const SCHEME_WRITE: &[(&str, Primitive)] = &[
+    ("display", prim_display),
+    ("write", prim_write),
+    ("write-shared", prim_write_shared),
+    ("write-simple", prim_write_simple),
+];
+
+const SCHEME_FILE: &[(&str, Primitive)] = &[
+    ("open-input-file", prim_open_input_file),
+    ("open-output-file", prim_open_output_file),
+    ("call-with-output-file", prim_call_with_output_file),
+];
+
+const SCHEME_LOAD: &[(&str, Primitive)] = &[("load", prim_load)];
+
+const SCHEME_READ: &[(&str, Primitive)] = &[("read", prim_read)];
+
+const SCHEME_EVAL: &[(&str, Primitive)] = &[("eval", prim_eval), ("environment", prim_environment)];
+
+const SCHEME_REPL: &[(&str, Primitive)] =
+    &[("interaction-environment", prim_interaction_environment)];
+
+const SCHEME_R5RS: &[(&str, Primitive)] = &[
+    ("scheme-report-environment", prim_scheme_report_environment),
+    ("null-environment", prim_null_environment),
+];
+
+const SRFI_1: &[(&str, Primitive)] = &[
+    ("iota", prim_iota),
+    ("filter", prim_filter),
+    ("remove", prim_remove),
+    ("reduce", prim_reduce),
+    ("fold-right", prim_fold_right),
+    ("last", prim_last),
+    ("take", prim_take),
+    ("drop", prim_drop),
+    ("any", prim_any),
+    ("every", prim_every),
+    ("find", prim_find),
+    ("delete", prim_delete),
+];
+
+const SRFI_69: &[(&str, Primitive)] = &[
+    ("make-hash-table", prim_make_hash_table),
+    ("hash-table?", prim_is_hash_table),
+    ("hash-table-ref", prim_hash_table_ref),
+    ("hash-table-ref/default", prim_hash_table_ref_default),
+    ("hash-table-set!", prim_hash_table_set),
+    ("hash-table-delete!", prim_hash_table_delete),
+    ("hash-table-contains?", prim_hash_table_contains),
+    ("hash-table-exists?", prim_hash_table_contains),
+    ("hash-table-update!", prim_hash_table_update),
+    ("hash-table-update!/default", prim_hash_table_update_default),
+    ("hash-table-size", prim_hash_table_size),
+    ("hash-table-keys", prim_hash_table_keys),
+    ("hash-table-values", prim_hash_table_values),
+    ("hash-table->alist", prim_hash_table_to_alist),
+    ("alist->hash-table", prim_alist_to_hash_table),
+    ("hash-table-walk", prim_hash_table_walk),
+];
+
+const SKIM_BASE: &[(&str, Primitive)] = &[
+    ("fold-left", prim_fold_left),
+    ("sort", prim_sort),
+    ("gensym", prim_gensym),
+    ("type-of", prim_type_of),
+    ("with-output-to-string", prim_with_output_to_string),
+    ("read-all", prim_read_all),
+    ("string->datum", prim_string_to_datum),
+    ("pretty-print", prim_pretty_print),
+];
+
+const SKIM_GC: &[(&str, Primitive)] = &[
+    ("gc", prim_gc),
+    ("gc-stats", prim_gc_stats),
+    ("set-heap-limit!", prim_set_heap_limit),
+];
+
+const SKIM_ENVIRONMENT: &[(&str, Primitive)] = &[
+    ("the-environment", prim_the_environment),
+    ("environment?", prim_is_environment),
+    ("environment-bound?", prim_environment_is_bound),
+    ("environment-ref", prim_environment_ref),
+    ("environment-define!", prim_environment_define),
+    ("environment-bound-names", prim_environment_bound_names),
+];
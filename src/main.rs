@@ -1,20 +1,182 @@
-use clap::{App, Arg};
-use skim::repl::repl;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use skim::format::{format_file, format_source};
+use skim::library::add_search_path;
+use skim::repl::{repl, Options};
 use skim::run::run;
+use skim::sandbox::{set_limits, Limits};
+use std::io::{self, Read};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+/// The stack size of the interpreter thread. Every nested procedure call takes
+/// a few kilobytes of stack, so this has to leave room for deep recursion.
+const STACK_SIZE: usize = 1 << 30;
+
+fn parse_arg<T: FromStr>(matches: &clap::ArgMatches, name: &str, what: &str) -> Option<T> {
+    matches.value_of(name).map(|value| match value.parse() {
+        Ok(value) => value,
+        Err(_) => {
+            eprintln!("invalid {}: {}", what, value);
+            std::process::exit(1);
+        }
+    })
+}
+
+/// Formats the files given to the `fmt` subcommand, or standard input if there
+/// are none. Returns false if formatting failed, or with `--check` if some
+/// file was not formatted.
+fn fmt(matches: &ArgMatches) -> bool {
+    let check = matches.is_present("check");
+    let files: Vec<&str> = matches.values_of("files").map(Iterator::collect).unwrap_or_default();
+    if files.is_empty() {
+        let mut code = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut code) {
+            eprintln!("could not read standard input: {}", e);
+            return false;
+        }
+        return match format_source(&code, None) {
+            Ok(formatted) if check => formatted == code,
+            Ok(formatted) => {
+                print!("{}", formatted);
+                true
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                false
+            }
+        };
+    }
+    let mut ok = true;
+    for file in files {
+        match format_file(file, check) {
+            Ok(true) => {}
+            Ok(false) if check => {
+                eprintln!("{} is not formatted", file);
+                ok = false;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("{}", e);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
 fn main() {
     let matches = App::new("skim-lisp")
         .version("0.0.1")
         .author("Jonathan Arns")
         .about("a small toy lisp implementation")
+        // after the interpreter's own arguments, `fmt` names a script
+        .setting(AppSettings::ArgsNegateSubcommands)
         .arg(
             Arg::with_name("file")
                 .index(1)
                 .help("The lisp script to execute"),
         )
+        .arg(
+            Arg::with_name("heap-limit")
+                .long("heap-limit")
+                .value_name("BYTES")
+                .help("Fail with an out of memory error once the heap grows beyond this size"),
+        )
+        .arg(
+            Arg::with_name("lib-path")
+                .long("lib-path")
+                .short("L")
+                .value_name("DIR")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Add a directory to search for libraries, before those in SKIM_LIBRARY_PATH"),
+        )
+        .arg(
+            Arg::with_name("no-init")
+                .long("no-init")
+                .help("Start the REPL without evaluating the init file"),
+        )
+        .arg(
+            Arg::with_name("sandbox")
+                .long("sandbox")
+                .help("Run untrusted code: no file access or standard input, and limits on steps, recursion, heap and time"),
+        )
+        .arg(
+            Arg::with_name("max-steps")
+                .long("max-steps")
+                .value_name("N")
+                .help("Fail once evaluation has made this many procedure calls"),
+        )
+        .arg(
+            Arg::with_name("max-depth")
+                .long("max-depth")
+                .value_name("N")
+                .help("Fail once this many procedure calls are nested"),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .value_name("MS")
+                .help("Fail once evaluation has taken this many milliseconds"),
+        )
+        .subcommand(
+            SubCommand::with_name("fmt")
+                .about("Formats lisp source files in place, or standard input to standard output")
+                .arg(
+                    Arg::with_name("check")
+                        .long("check")
+                        .help("Only check that the files are formatted, and fail if they are not"),
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .index(1)
+                        .multiple(true)
+                        .help("The files to format"),
+                ),
+        )
         .get_matches();
 
-    match matches.value_of("file") {
-        None => repl(),
-        Some(file) => run(file),
+    if let Some(matches) = matches.subcommand_matches("fmt") {
+        std::process::exit(if fmt(matches) { 0 } else { 1 });
+    }
+
+    let sandbox = matches.is_present("sandbox");
+    let no_init = matches.is_present("no-init");
+    let mut limits = if sandbox { Limits::sandbox() } else { Limits::default() };
+    if let Some(bytes) = parse_arg(&matches, "heap-limit", "heap limit") {
+        limits.heap = Some(bytes);
+    }
+    if let Some(steps) = parse_arg(&matches, "max-steps", "step limit") {
+        limits.steps = Some(steps);
+    }
+    if let Some(depth) = parse_arg(&matches, "max-depth", "recursion limit") {
+        limits.depth = Some(depth);
+    }
+    if let Some(ms) = parse_arg(&matches, "timeout", "timeout") {
+        limits.time = Some(Duration::from_millis(ms));
+    }
+
+    let lib_path: Vec<String> = matches
+        .values_of("lib-path")
+        .map(|dirs| dirs.map(str::to_owned).collect())
+        .unwrap_or_default();
+    let file = matches.value_of("file").map(str::to_owned);
+    let interpreter = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            // the library search path and the limits are per thread
+            for dir in lib_path {
+                add_search_path(dir);
+            }
+            set_limits(&limits);
+            match file {
+                None => repl(Options { sandbox, no_init }),
+                Some(file) => run(&file, sandbox),
+            }
+        })
+        .expect("could not start the interpreter thread");
+    if interpreter.join().is_err() {
+        std::process::exit(1);
     }
 }
@@ -1,5 +1,11 @@
 use crate::ast::*;
+use crate::gc::Gc;
+use crate::symbol::Symbol;
 use crate::Exception::*;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::rc::Rc;
 
 #[derive(Clone)]
 pub struct Token {
@@ -8,106 +14,524 @@ pub struct Token {
 }
 
 impl Token {
-    fn new(line: usize, position: usize, c: char, file: Option<String>, code: String) -> Token {
-        let mut s = String::new();
-        s.push(c);
-        Token {
-            meta: Meta::new(line, position, 1, file, code),
-            string: s,
+    pub fn meta(&self) -> &Meta {
+        &self.meta
+    }
+
+    pub(crate) fn text(&self) -> &str {
+        &self.string
+    }
+
+    fn is_unterminated_string(&self) -> bool {
+        if !self.string.starts_with('"') || self.string.len() < 2 || !self.string.ends_with('"') {
+            return self.string.starts_with('"');
         }
+        // the closing quote must not be escaped
+        let backslashes = self.string[1..self.string.len() - 1]
+            .chars()
+            .rev()
+            .take_while(|c| *c == '\\')
+            .count();
+        backslashes % 2 == 1
     }
 }
 
-pub fn lex(code: &str, file: Option<String>) -> Vec<Token> {
-    let mut res: Vec<Token> = Vec::new();
-    let mut token = None;
-    let mut line = 0;
-    let mut position;
-    for l in code.split_terminator("\n") {
-        line += 1;
-        position = 0;
-        for c in l.chars() {
-            position += 1;
-            match c {
-                ';' => break,
-                ' ' => {
-                    if let Some(t) = token {
-                        res.push(t);
-                        token = None;
+/// Returns true if `tokens` end in the middle of a datum, like in an unclosed
+/// list or string, so that more input could complete them.
+pub fn is_incomplete(tokens: &[Token]) -> bool {
+    let mut depth = 0;
+    for token in tokens {
+        match &token.string[..] {
+            "(" | "#(" => depth += 1,
+            ")" => depth -= 1,
+            _ => {}
+        }
+    }
+    match tokens.last() {
+        Some(last) => {
+            depth > 0
+                || last.string == "'"
+                || last.string == "#;"
+                || last.string == "#|"
+                || label(&last.string, '=').is_some()
+                || last.is_unterminated_string()
+        }
+        None => false,
+    }
+}
+
+/// What is between tokens, which `lex` skips and the syntax tree of the
+/// `cst` module keeps.
+#[derive(Clone)]
+pub(crate) enum Trivia {
+    Whitespace(String),
+    /// A comment from `;` to the end of the line, without the line break.
+    LineComment(String),
+    /// A comment between `#|` and `|#`, which may contain other block
+    /// comments.
+    BlockComment(String),
+    /// `#;` with the datum it comments out.
+    DatumComment(String),
+}
+
+impl Trivia {
+    pub fn text(&self) -> &str {
+        match self {
+            Trivia::Whitespace(text)
+            | Trivia::LineComment(text)
+            | Trivia::BlockComment(text)
+            | Trivia::DatumComment(text) => text,
+        }
+    }
+}
+
+/// What source is split into.
+pub(crate) enum Piece {
+    Token(Token),
+    Trivia(Trivia),
+    /// The `#;` of a datum comment, which comments out the datum after it.
+    DatumComment(Token),
+}
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || "()'\";".contains(c)
+}
+
+/// Gives the next line of source without its line break, `None` at the end.
+pub(crate) type Lines = Box<dyn FnMut() -> io::Result<Option<String>>>;
+
+/// Splits source into tokens and the trivia between them, keeping track of
+/// where they are for the `Meta` of tokens.
+pub(crate) struct Scanner {
+    chars: Vec<char>,
+    i: usize,
+    line: usize,
+    position: usize,
+    lines: Vec<Rc<str>>,
+    file: Option<Rc<str>>,
+    /// Where more source comes from once `chars` is used up.
+    more: Option<Lines>,
+    /// The `#|` of a block comment that was still open at the end.
+    pub unterminated: Option<Token>,
+    /// The error that ended reading from `more`.
+    pub error: Option<io::Error>,
+}
+
+impl Scanner {
+    pub fn new(code: &str, file: Option<&str>) -> Scanner {
+        Scanner {
+            chars: code.chars().collect(),
+            i: 0,
+            line: 1,
+            position: 0,
+            lines: code.split('\n').map(Into::into).collect(),
+            file: file.map(Into::into),
+            more: None,
+            unterminated: None,
+            error: None,
+        }
+    }
+
+    /// A scanner of source that is read a line at a time, as far as the
+    /// pieces asked for need it.
+    pub fn lines(more: Lines) -> Scanner {
+        Scanner {
+            chars: Vec::new(),
+            i: 0,
+            line: 1,
+            position: 0,
+            lines: Vec::new(),
+            file: None,
+            more: Some(more),
+            unterminated: None,
+            error: None,
+        }
+    }
+
+    /// The source that has been read but not scanned yet.
+    pub fn rest(&self) -> String {
+        self.chars[self.i..].iter().collect()
+    }
+
+    fn peek(&mut self, offset: usize) -> Option<char> {
+        while self.i + offset >= self.chars.len() {
+            let more = self.more.as_mut()?;
+            match more() {
+                Ok(Some(line)) => {
+                    self.chars.extend(line.chars());
+                    self.chars.push('\n');
+                    self.lines.push(line.into());
+                }
+                Ok(None) => self.more = None,
+                Err(e) => {
+                    self.more = None;
+                    self.error = Some(e);
+                }
+            }
+        }
+        self.chars.get(self.i + offset).copied()
+    }
+
+    fn bump(&mut self, text: &mut String) {
+        let c = self.chars[self.i];
+        self.i += 1;
+        text.push(c);
+        if c == '\n' {
+            self.line += 1;
+            self.position = 0;
+        } else {
+            self.position += 1;
+        }
+    }
+
+    /// The token `string` that starts at `line` and `position`. Only the part
+    /// of a string literal on its first line is pointed at.
+    fn token(&self, line: usize, position: usize, string: String) -> Token {
+        let code = self.lines.get(line - 1).cloned().unwrap_or_else(|| "".into());
+        let length = string.split('\n').next().unwrap_or_default().chars().count();
+        Token {
+            meta: Meta::new(line, position, length, self.file.clone(), code),
+            string,
+        }
+    }
+
+    pub fn next(&mut self) -> Option<Piece> {
+        let c = self.peek(0)?;
+        let (line, position) = (self.line, self.position + 1);
+        let mut text = String::new();
+        let piece = match (c, self.peek(1)) {
+            (c, _) if c.is_whitespace() => {
+                while self.peek(0).is_some_and(char::is_whitespace) {
+                    self.bump(&mut text);
+                }
+                Piece::Trivia(Trivia::Whitespace(text))
+            }
+            (';', _) => {
+                while self.peek(0).is_some_and(|c| c != '\n') {
+                    self.bump(&mut text);
+                }
+                Piece::Trivia(Trivia::LineComment(text))
+            }
+            ('#', Some('|')) => {
+                let mut depth = 0;
+                loop {
+                    match (self.peek(0), self.peek(1)) {
+                        (Some('#'), Some('|')) => depth += 1,
+                        (Some('|'), Some('#')) => depth -= 1,
+                        (None, _) => {
+                            self.unterminated = Some(self.token(line, position, "#|".into()));
+                            break;
+                        }
+                        _ => {
+                            self.bump(&mut text);
+                            continue;
+                        }
+                    }
+                    self.bump(&mut text);
+                    self.bump(&mut text);
+                    if depth == 0 {
+                        break;
                     }
                 }
-                '(' | ')' | '\'' => {
-                    if let Some(t) = token {
-                        res.push(t);
-                        token = None;
+                Piece::Trivia(Trivia::BlockComment(text))
+            }
+            ('#', Some(';')) => {
+                self.bump(&mut text);
+                self.bump(&mut text);
+                Piece::DatumComment(self.token(line, position, text))
+            }
+            _ => {
+                self.atom(&mut text);
+                Piece::Token(self.token(line, position, text))
+            }
+        };
+        Some(piece)
+    }
+
+    /// Reads the text of a token that is not trivia into `text`.
+    fn atom(&mut self, text: &mut String) {
+        match (self.peek(0), self.peek(1)) {
+            (Some('('), _) | (Some(')'), _) | (Some('\''), _) => self.bump(text),
+            (Some('#'), Some('(')) => {
+                self.bump(text);
+                self.bump(text);
+            }
+            (Some('"'), _) => {
+                self.bump(text);
+                let mut escaped = false;
+                while let Some(c) = self.peek(0) {
+                    self.bump(text);
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => break,
+                        _ => {}
                     }
-                    res.push(Token::new(line, position, c, file.clone(), l.to_owned()));
                 }
-                _ => {
-                    if let Some(ref mut t) = token {
-                        t.string.push(c);
-                        t.meta.token_length += 1;
-                    } else {
-                        token = Some(Token::new(line, position, c, file.clone(), l.to_owned()));
+            }
+            _ => {
+                // the character of a character literal may be a delimiter
+                let literal = (self.peek(0), self.peek(1)) == (Some('#'), Some('\\'));
+                if literal && self.peek(2).is_some() {
+                    for _ in 0..3 {
+                        self.bump(text);
+                    }
+                }
+                while self.peek(0).is_some_and(|c| !is_delimiter(c)) {
+                    self.bump(text);
+                    // a datum label like #0= ends right before its datum
+                    if label(text, '=').is_some() {
+                        break;
                     }
                 }
             }
         }
     }
+}
+
+pub fn lex(code: &str, file: Option<String>) -> Vec<Token> {
+    let mut scanner = Scanner::new(code, file.as_deref());
+    let mut res = Vec::new();
+    while let Some(piece) = scanner.next() {
+        match piece {
+            Piece::Token(token) | Piece::DatumComment(token) => res.push(token),
+            Piece::Trivia(_) => {}
+        }
+    }
+    // an unterminated block comment is left for the parser to report
+    res.extend(scanner.unterminated);
+    skip_datum_comments(res)
+}
+
+/// Removes the datum comments `#;` in `tokens` along with the data they
+/// comment out. One without a complete datum after it is left in place.
+pub(crate) fn skip_datum_comments(tokens: Vec<Token>) -> Vec<Token> {
+    let mut res = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if tokens[i].string == "#;" {
+            if let Some(end) = datum_end(&tokens, i + 1) {
+                i = end;
+                continue;
+            }
+        }
+        res.push(tokens[i].clone());
+        i += 1;
+    }
     res
 }
 
-pub fn parse<'a>(tokens: &'a [Token]) -> Result<(Item, &'a [Token]), Exn> {
+/// The index of the token after the datum that starts at `start`, if there is
+/// a complete one.
+fn datum_end(tokens: &[Token], start: usize) -> Option<usize> {
+    let token = tokens.get(start)?;
+    match &token.string[..] {
+        "(" | "#(" => {
+            let mut i = start + 1;
+            while tokens.get(i)?.string != ")" {
+                i = datum_end(tokens, i)?;
+            }
+            Some(i + 1)
+        }
+        ")" | "#|" => None,
+        // a datum comment is followed by the datum it comments out, and then
+        // by the datum it stands before
+        "#;" => datum_end(tokens, datum_end(tokens, start + 1)?),
+        s if s == "'" || label(s, '=').is_some() => datum_end(tokens, start + 1),
+        _ => Some(start + 1),
+    }
+}
+
+/// Returns the number of the datum label `token`, like `#0=` if `end` is `=`
+/// or `#0#` if it is `#`.
+pub(crate) fn label(token: &str, end: char) -> Option<usize> {
+    token.strip_prefix('#')?.strip_suffix(end)?.parse().ok()
+}
+
+/// The objects that datum labels refer to, by their numbers.
+type Labels = HashMap<usize, Exp>;
+
+pub fn parse(tokens: &[Token]) -> Result<(Item, &[Token]), Exn> {
+    // labels are local to the outermost datum they are defined in
+    parse_datum(tokens, &mut Labels::new())
+}
+
+fn parse_datum<'t>(tokens: &'t [Token], labels: &mut Labels) -> Result<(Item, &'t [Token]), Exn> {
     let (token, rest) = tokens
         .split_first()
         .ok_or(Exn::other(Meta::empty(), "Could not get next token"))?;
     match &token.string[..] {
-        "(" => parse_list(rest, token.meta.clone()),
+        "(" => parse_list(rest, token.meta.clone(), labels),
+        "#(" => parse_vector(rest, token.meta.clone(), labels),
         ")" => Err(Exn::syntax(token.meta.clone(), "Found unexpected \")\"")),
-        "'" => parse_quote(rest, token.meta.clone()),
-        _ => Ok((parse_atom(token.to_owned())?, rest)),
+        "'" => parse_quote(rest, token.meta.clone(), labels),
+        "#;" => Err(Exn::syntax(token.meta.clone(), "Expected a datum after #;")),
+        "#|" => Err(Exn::syntax(token.meta.clone(), "Unterminated block comment")),
+        s => {
+            if let Some(n) = label(s, '=') {
+                parse_labelled(rest, token.meta.clone(), n, labels)
+            } else if let Some(n) = label(s, '#') {
+                match labels.get(&n) {
+                    Some(exp) => Ok((Item::new(token.meta.clone(), exp.clone()), rest)),
+                    None => Err(Exn::syntax(
+                        token.meta.clone(),
+                        &format!("Undefined datum label #{}#", n),
+                    )),
+                }
+            } else {
+                Ok((parse_atom(token.to_owned())?, rest))
+            }
+        }
     }
 }
 
-fn parse_quote<'a>(tokens: &'a [Token], meta: Meta) -> Result<(Item, &'a [Token]), Exn> {
-    let (datum, rest) = parse(tokens)?;
+/// Parses the datum labelled `#n=`, which may refer to itself with `#n#`.
+fn parse_labelled<'t>(
+    tokens: &'t [Token],
+    meta: Meta,
+    n: usize,
+    labels: &mut Labels,
+) -> Result<(Item, &'t [Token]), Exn> {
+    // references within the datum point to a placeholder until it is done
+    let nil = Item::new(meta.clone(), Exp::Nil);
+    let placeholder = cons(nil.clone(), nil);
+    labels.insert(n, Exp::Pair(placeholder.clone()));
+    let (datum, rest) = parse_datum(tokens, labels)?;
+    if let Exp::Pair(cell) = &datum.exp {
+        if Gc::ptr_eq(cell, &placeholder) {
+            return Err(Exn::syntax(meta, "A datum label can't label only itself"));
+        }
+    }
+    replace(&datum.exp, &placeholder);
+    labels.insert(n, datum.exp.clone());
+    Ok((datum, rest))
+}
+
+/// Replaces all references to `placeholder` within `datum` by `datum`.
+fn replace(datum: &Exp, placeholder: &Gc<LispCell>) {
+    let is_placeholder =
+        |exp: &Exp| matches!(exp, Exp::Pair(cell) if Gc::ptr_eq(cell, placeholder));
+    let mut seen = HashSet::new();
+    let mut stack = vec![datum.clone()];
+    while let Some(exp) = stack.pop() {
+        match exp {
+            Exp::Pair(cell) if seen.insert(cell.addr()) => {
+                let (car, cdr) = (cell.car(), cell.cdr());
+                if is_placeholder(&car.exp) {
+                    cell.set_car(Item::new(car.meta, datum.clone()));
+                } else {
+                    stack.push(car.exp);
+                }
+                if is_placeholder(&cdr.exp) {
+                    cell.set_cdr(Item::new(cdr.meta, datum.clone()));
+                } else {
+                    stack.push(cdr.exp);
+                }
+            }
+            Exp::Vector(vec) if seen.insert(vec.addr()) => {
+                for exp in vec.borrow_mut().iter_mut() {
+                    if is_placeholder(exp) {
+                        *exp = datum.clone();
+                    } else {
+                        stack.push(exp.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_vector<'t>(
+    tokens: &'t [Token],
+    mut meta: Meta,
+    labels: &mut Labels,
+) -> Result<(Item, &'t [Token]), Exn> {
+    let mut items = Vec::new();
+    let mut toks = tokens;
+    loop {
+        let (next, rest) = toks
+            .split_first()
+            .ok_or(Exn::other(Meta::empty(), "Could not get next token"))?;
+        if next.string == ")" {
+            meta.token_length = 1 + next.meta.position - meta.position;
+            let vec = Exp::Vector(Gc::new(RefCell::new(items)));
+            return Ok((Item::new(meta, vec), rest));
+        }
+        let (item, new_toks) = parse_datum(toks, labels)?;
+        items.push(item.exp);
+        toks = new_toks;
+    }
+}
+
+fn parse_quote<'t>(
+    tokens: &'t [Token],
+    meta: Meta,
+    labels: &mut Labels,
+) -> Result<(Item, &'t [Token]), Exn> {
+    let (datum, rest) = parse_datum(tokens, labels)?;
     let datum_meta = datum.meta.clone();
     Ok((
         Item::cons(
             meta.clone(),
-            Item::new(meta, Exp::Symbol("quote".to_string())),
+            Item::new(meta, Exp::Symbol(Symbol::QUOTE)),
             Item::cons(datum_meta.clone(), datum, Item::new(datum_meta, Exp::Nil)),
         ),
         rest,
     ))
 }
 
-fn parse_list<'a>(tokens: &'a [Token], mut meta: Meta) -> Result<(Item, &'a [Token]), Exn> {
-    let mut list = cons(
-        Item::new(meta.clone(), Exp::Nil),
-        Item::new(meta.clone(), Exp::Nil),
-    );
+fn parse_list<'t>(
+    tokens: &'t [Token],
+    mut meta: Meta,
+    labels: &mut Labels,
+) -> Result<(Item, &'t [Token]), Exn> {
+    let mut items = Vec::new();
     let mut toks = tokens;
     loop {
         let (next, rest) = toks
             .split_first()
             .ok_or(Exn::other(Meta::empty(), "Could not get next token"))?;
         if next.string == ")" {
-            meta.token_length = 1 + next.meta.position - meta.position;
-            if let (&Exp::Nil, &Exp::Nil) = (&list.car.exp, &list.cdr.exp) {
-                return Ok((Item::new(meta, Exp::Pair(list)), rest));
-            } else if let Exp::Pair(res) = list.cdr.exp {
-                return Ok((Item::new(meta, Exp::Pair(res)), rest));
-            } else {
-                return Err(Exn::syntax(next.meta.clone(), "Could not parse list"));
+            span(&mut meta, &next.meta);
+            return Ok((Item::list(meta, items), rest));
+        }
+        if next.string == "." {
+            if items.is_empty() {
+                return Err(Exn::syntax(next.meta.clone(), "Found unexpected \".\""));
+            }
+            let (mut list, rest) = parse_datum(rest, labels)?;
+            let (close, rest) = rest
+                .split_first()
+                .ok_or(Exn::other(Meta::empty(), "Could not get next token"))?;
+            if close.string != ")" {
+                return Err(Exn::syntax(
+                    close.meta.clone(),
+                    "Expected \")\" after the last element of a dotted list",
+                ));
             }
+            span(&mut meta, &close.meta);
+            for item in items.into_iter().rev() {
+                list = Item::cons(meta.clone(), item, list);
+            }
+            return Ok((list, rest));
         }
-        let (exp, new_toks) = parse(toks)?;
-        list.append(exp)?;
+        let (exp, new_toks) = parse_datum(toks, labels)?;
+        items.push(exp);
         toks = new_toks;
     }
 }
 
+/// Points the `meta` of a list's opening parenthesis at the list up to its
+/// closing one at `close`, or to the end of the line if it closes on another.
+fn span(meta: &mut Meta, close: &Meta) {
+    meta.token_length = if close.line == meta.line {
+        1 + close.position - meta.position
+    } else {
+        1 + meta.code.chars().count() - meta.position
+    };
+}
+
 fn parse_atom(token: Token) -> Result<Item, Exn> {
     let mut iter = token.string.chars();
     let first = iter.next().ok_or(Exn::other(
@@ -122,26 +546,68 @@ fn parse_atom(token: Token) -> Result<Item, Exn> {
             Ok(Item::new(token.meta, Exp::Boolean(true)))
         } else if token.string == "#f" {
             Ok(Item::new(token.meta, Exp::Boolean(false)))
+        } else if let Some(name) = token.string.strip_prefix("#\\") {
+            match parse_char(name) {
+                Some(c) => Ok(Item::new(token.meta, Exp::Char(c))),
+                None => Err(Exn::syntax(
+                    token.meta,
+                    &format!("Unknown character: #\\{}", name),
+                )),
+            }
         } else {
             Err(Exn::syntax(
                 token.meta,
                 &format!("Unexpected token: {}", token.string),
             ))
         }
-    // } else if first == '"' {
-    //     // string
-    //     let mut string = String::new();
-    //     for c in iter {
-    //         if c != '"' || string.ends_with("\"") {
-    //             string.push(c);
-    //         } else {
-    //             return Ok(Exp::String(string));
-    //         }
-    //     }
-    //     Err(LispErr::UnexpectedToken(
-    //         "Expected \" to finish string literal".to_string(),
-    //     ))
+    } else if first == '"' {
+        parse_string(token)
     } else {
-        Ok(Item::new(token.meta, Exp::Symbol(token.string)))
+        Ok(Item::new(token.meta, Exp::Symbol(Symbol::intern(&token.string))))
+    }
+}
+
+fn parse_string(token: Token) -> Result<Item, Exn> {
+    let mut string = String::new();
+    let mut chars = token.string.chars().skip(1);
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Ok(Item::new(token.meta, Exp::String(Gc::new(string)))),
+            '\\' => string.push(match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('0') => '\0',
+                Some(c @ '\\') | Some(c @ '"') => c,
+                Some(c) => {
+                    return Err(Exn::syntax(
+                        token.meta,
+                        &format!("Unknown escape sequence \\{} in string", c),
+                    ))
+                }
+                None => break,
+            }),
+            c => string.push(c),
+        }
+    }
+    Err(Exn::syntax(
+        token.meta,
+        "Expected \" to finish string literal",
+    ))
+}
+
+/// Parses the part of a character literal after `#\\`, which is a single
+/// character, a character name or a hex scalar value like `x41`.
+fn parse_char(name: &str) -> Option<char> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        (Some('x'), Some(_)) => u32::from_str_radix(&name[1..], 16)
+            .ok()
+            .and_then(std::char::from_u32),
+        _ => CHAR_NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, c)| *c),
     }
 }
@@ -0,0 +1,272 @@
+use crate::gc::{Gc, Trace};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Write};
+
+/// A source of characters or a sink for them (R7RS 6.13).
+pub struct Port {
+    /// What the port reads from or writes to, like a file name.
+    pub name: String,
+    pub is_input: bool,
+    // `None` once the port is closed
+    stream: RefCell<Option<Stream>>,
+}
+
+enum Stream {
+    Reader {
+        reader: Box<dyn BufRead>,
+        // characters to read before those from `reader`, in reverse order
+        unread: Vec<char>,
+    },
+    Writer {
+        writer: Box<dyn Write>,
+        // console output is flushed right away
+        flush: bool,
+    },
+    String(String),
+}
+
+impl Port {
+    fn new(name: &str, is_input: bool, stream: Stream) -> Port {
+        Port {
+            name: name.to_string(),
+            is_input,
+            stream: RefCell::new(Some(stream)),
+        }
+    }
+
+    fn reader(name: &str, reader: Box<dyn BufRead>) -> Port {
+        Port::new(
+            name,
+            true,
+            Stream::Reader {
+                reader,
+                unread: Vec::new(),
+            },
+        )
+    }
+
+    fn writer(name: &str, writer: Box<dyn Write>, flush: bool) -> Port {
+        Port::new(name, false, Stream::Writer { writer, flush })
+    }
+
+    pub fn open_input_file(path: &str) -> io::Result<Port> {
+        let file = File::open(path)?;
+        Ok(Port::reader(path, Box::new(BufReader::new(file))))
+    }
+
+    pub fn open_output_file(path: &str) -> io::Result<Port> {
+        let file = File::create(path)?;
+        Ok(Port::writer(path, Box::new(BufWriter::new(file)), false))
+    }
+
+    pub fn input_string(string: &str) -> Port {
+        let cursor = Cursor::new(string.as_bytes().to_vec());
+        Port::reader("string", Box::new(cursor))
+    }
+
+    /// An output port collecting everything written to it in a string.
+    pub fn output_string() -> Port {
+        Port::new("string", false, Stream::String(String::new()))
+    }
+
+    /// Flushes and closes the port. Closing a closed port does nothing.
+    pub fn close(&self) -> io::Result<()> {
+        match self.stream.borrow_mut().take() {
+            Some(Stream::Writer { mut writer, .. }) => writer.flush(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the next character without consuming it, `None` at the end of input.
+    pub fn peek_char(&self) -> io::Result<Option<char>> {
+        match &mut *self.stream.borrow_mut() {
+            Some(Stream::Reader { reader, unread }) => {
+                if unread.is_empty() {
+                    unread.extend(next_char(reader)?);
+                }
+                Ok(unread.last().copied())
+            }
+            Some(_) => Err(not_input()),
+            None => Err(closed()),
+        }
+    }
+
+    /// Returns the next character, `None` at the end of input.
+    pub fn read_char(&self) -> io::Result<Option<char>> {
+        match &mut *self.stream.borrow_mut() {
+            Some(Stream::Reader { reader, unread }) => match unread.pop() {
+                Some(c) => Ok(Some(c)),
+                None => next_char(reader),
+            },
+            Some(_) => Err(not_input()),
+            None => Err(closed()),
+        }
+    }
+
+    /// Reads up to the next line ending, which is consumed but not returned.
+    /// Returns `None` at the end of input.
+    pub fn read_line(&self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        loop {
+            match self.read_char()? {
+                Some('\n') => break,
+                Some(c) => line.push(c),
+                None if line.is_empty() => return Ok(None),
+                None => break,
+            }
+        }
+        if line.ends_with('\r') {
+            line.pop();
+        }
+        Ok(Some(line))
+    }
+
+    /// Reads up to `k` characters. Returns `None` at the end of input.
+    pub fn read_string(&self, k: usize) -> io::Result<Option<String>> {
+        let mut string = String::new();
+        for _ in 0..k {
+            match self.read_char()? {
+                Some(c) => string.push(c),
+                None => break,
+            }
+        }
+        if string.is_empty() && k > 0 {
+            return Ok(None);
+        }
+        Ok(Some(string))
+    }
+
+    /// Puts `s` back, so that it is read again before the rest of the input.
+    pub fn unread(&self, s: &str) -> io::Result<()> {
+        match &mut *self.stream.borrow_mut() {
+            Some(Stream::Reader { unread, .. }) => {
+                unread.extend(s.chars().rev());
+                Ok(())
+            }
+            Some(_) => Err(not_input()),
+            None => Err(closed()),
+        }
+    }
+
+    pub fn write_str(&self, s: &str) -> io::Result<()> {
+        match &mut *self.stream.borrow_mut() {
+            Some(Stream::Writer { writer, flush }) => {
+                writer.write_all(s.as_bytes())?;
+                if *flush {
+                    writer.flush()?;
+                }
+                Ok(())
+            }
+            Some(Stream::String(string)) => {
+                string.push_str(s);
+                Ok(())
+            }
+            Some(_) => Err(not_output()),
+            None => Err(closed()),
+        }
+    }
+
+    /// The output collected by a string port so far.
+    pub fn contents(&self) -> Option<String> {
+        match &*self.stream.borrow() {
+            Some(Stream::String(string)) => Some(string.clone()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn format(&self) -> String {
+        let kind = if self.is_input { "input" } else { "output" };
+        format!("#<{}-port {}>", kind, self.name)
+    }
+}
+
+/// Decodes the next UTF-8 character from `reader`.
+fn next_char(reader: &mut Box<dyn BufRead>) -> io::Result<Option<char>> {
+    let mut buf = [0u8; 4];
+    if reader.read(&mut buf[..1])? == 0 {
+        return Ok(None);
+    }
+    let len = match buf[0] {
+        b if b < 0x80 => 1,
+        b if b >> 5 == 0b110 => 2,
+        b if b >> 4 == 0b1110 => 3,
+        b if b >> 3 == 0b11110 => 4,
+        _ => return Err(invalid_utf8()),
+    };
+    reader.read_exact(&mut buf[1..len])?;
+    match std::str::from_utf8(&buf[..len]) {
+        Ok(s) => Ok(s.chars().next()),
+        Err(_) => Err(invalid_utf8()),
+    }
+}
+
+fn invalid_utf8() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "stream did not contain valid UTF-8",
+    )
+}
+
+fn not_input() -> io::Error {
+    io::Error::other("not an input port")
+}
+
+fn not_output() -> io::Error {
+    io::Error::other("not an output port")
+}
+
+fn closed() -> io::Error {
+    io::Error::other("port is closed")
+}
+
+impl Drop for Port {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+impl Trace for Port {
+    fn trace(&self, _: &mut dyn FnMut(usize)) -> bool {
+        true
+    }
+}
+
+struct CurrentPorts {
+    input: RefCell<Gc<Port>>,
+    output: RefCell<Gc<Port>>,
+    error: RefCell<Gc<Port>>,
+}
+
+thread_local! {
+    static CURRENT: CurrentPorts = CurrentPorts {
+        input: RefCell::new(Gc::new(Port::reader(
+            "stdin",
+            Box::new(BufReader::new(io::stdin())),
+        ))),
+        output: RefCell::new(Gc::new(Port::writer("stdout", Box::new(io::stdout()), true))),
+        error: RefCell::new(Gc::new(Port::writer("stderr", Box::new(io::stderr()), true))),
+    };
+}
+
+pub fn current_input() -> Gc<Port> {
+    CURRENT.with(|ports| ports.input.borrow().clone())
+}
+
+pub fn current_output() -> Gc<Port> {
+    CURRENT.with(|ports| ports.output.borrow().clone())
+}
+
+pub fn current_error() -> Gc<Port> {
+    CURRENT.with(|ports| ports.error.borrow().clone())
+}
+
+/// Makes `port` the current input port and returns the previous one.
+pub fn replace_current_input(port: Gc<Port>) -> Gc<Port> {
+    CURRENT.with(|ports| ports.input.replace(port))
+}
+
+/// Makes `port` the current output port and returns the previous one.
+pub fn replace_current_output(port: Gc<Port>) -> Gc<Port> {
+    CURRENT.with(|ports| ports.output.replace(port))
+}
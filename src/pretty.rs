@@ -0,0 +1,286 @@
+//! A pretty printer for values, after Wadler's "A prettier printer".
+//!
+//! A value is first turned into a document that says where lines may be
+//! broken, which is then laid out to fit a width: a group is printed on one
+//! line if it fits, and otherwise every line break directly in it is taken.
+use crate::ast::*;
+use crate::write::{self, Label, Labels, Sharing};
+
+pub(crate) enum Doc {
+    Text(String),
+    /// A space, or a line break followed by the indentation.
+    Line,
+    /// Indents the lines broken in the document by this many more columns.
+    Nest(usize, Box<Doc>),
+    /// Indents the lines broken in the document to the column it starts in.
+    Align(Box<Doc>),
+    /// Lays the document out on one line if it fits.
+    Group(Box<Doc>),
+    Concat(Vec<Doc>),
+    /// Keeps the groups it is in from being laid out on one line, like a line
+    /// comment has to.
+    Break,
+    /// An empty line, in a group that is not laid out on one line.
+    BlankLine,
+}
+
+impl Doc {
+    pub fn text(text: &str) -> Doc {
+        Doc::Text(text.to_string())
+    }
+
+    fn nest(indent: usize, doc: Doc) -> Doc {
+        Doc::Nest(indent, Box::new(doc))
+    }
+
+    fn align(doc: Doc) -> Doc {
+        Doc::Align(Box::new(doc))
+    }
+
+    fn group(doc: Doc) -> Doc {
+        Doc::Group(Box::new(doc))
+    }
+}
+
+/// How the forms starting with a keyword are laid out when they don't fit on
+/// one line.
+pub(crate) enum Form {
+    /// The keyword is followed by this many operands on its line, and the
+    /// body is indented below it.
+    Body(usize),
+    /// The clauses are aligned below each other and laid out as data, even
+    /// those starting with a symbol like `else`.
+    Clauses,
+}
+
+/// The form of the special forms starting with `keyword`. Whether their first
+/// operand is a symbol tells a named let from a plain one.
+pub(crate) fn form(keyword: &str, symbol_operand: bool) -> Option<Form> {
+    match keyword {
+        // a named let has its name before the bindings
+        "let" if symbol_operand => Some(Form::Body(2)),
+        "define" | "lambda" | "let" | "let*" | "letrec" | "letrec*" => Some(Form::Body(1)),
+        "cond" => Some(Form::Clauses),
+        _ => None,
+    }
+}
+
+/// The document for `exp` as `write` shows it, with the objects in `labels`
+/// labelled.
+pub(crate) fn doc(exp: &Exp, labels: &mut Labels) -> Doc {
+    layout(exp, false, labels)
+}
+
+/// The document for `exp`, where a list is laid out as data rather than code
+/// if `data` is true, so that it does not get the layout of a call or special
+/// form.
+fn layout(exp: &Exp, data: bool, labels: &mut Labels) -> Doc {
+    if !matches!(exp, Exp::Pair(_) | Exp::Vector(_)) {
+        return Doc::Text(write::format(exp, true, labels));
+    }
+    let label = labels.label(exp);
+    if let Some(reference @ Label::Reference(_)) = &label {
+        return Doc::Text(reference.to_string());
+    }
+    let doc = match exp {
+        Exp::Vector(vec) => {
+            let items = vec.borrow().iter().map(|exp| doc(exp, labels)).collect();
+            list("#(", items, ")")
+        }
+        _ => pair(exp, data, labels),
+    };
+    match label {
+        Some(label) => Doc::Concat(vec![Doc::Text(label.to_string()), doc]),
+        None => doc,
+    }
+}
+
+fn pair(exp: &Exp, data: bool, labels: &mut Labels) -> Doc {
+    let (items, tail) = elements(exp, labels);
+    let head = match &items[0] {
+        Exp::Symbol(s) if !data => s.name(),
+        _ => return list("(", docs(&items, &tail, false, labels), ")"),
+    };
+    let open = format!("({} ", head);
+    let symbol_operand = matches!(items.get(1), Some(Exp::Symbol(_)));
+    match form(&head, symbol_operand) {
+        Some(Form::Body(n)) if tail.is_none() && items.len() > n + 1 => {
+            // in the order they are written, for the datum labels
+            let operands = docs(&items[1..=n], &None, false, labels);
+            let rest = docs(&items[n + 1..], &None, false, labels);
+            body(&head, operands, rest)
+        }
+        Some(Form::Clauses) => list(&open, docs(&items[1..], &tail, true, labels), ")"),
+        _ if items.len() == 1 && tail.is_none() => Doc::Text(format!("({})", head)),
+        _ => list(&open, docs(&items[1..], &tail, false, labels), ")"),
+    }
+}
+
+/// The elements of the list `exp` and the tail of an improper list. A tail
+/// that is labelled is left in the tail, where its label can be written.
+fn elements(exp: &Exp, labels: &Labels) -> (Vec<Exp>, Option<Exp>) {
+    let mut items = Vec::new();
+    let mut rest = exp.clone();
+    loop {
+        match rest {
+            Exp::Pair(ref cell) if items.is_empty() || !labels.is_labelled(&rest) => {
+                items.push(cell.car().exp);
+                rest = cell.cdr().exp;
+            }
+            Exp::Nil => return (items, None),
+            tail => return (items, Some(tail)),
+        }
+    }
+}
+
+fn docs(items: &[Exp], tail: &Option<Exp>, data: bool, labels: &mut Labels) -> Vec<Doc> {
+    let mut docs: Vec<Doc> = items.iter().map(|exp| layout(exp, data, labels)).collect();
+    if let Some(tail) = tail {
+        docs.push(Doc::text("."));
+        docs.push(doc(tail, labels));
+    }
+    docs
+}
+
+/// Lays out a special form with its `operands` on the line of `keyword` and
+/// its `body` indented by two columns below.
+pub(crate) fn body(keyword: &str, operands: Vec<Doc>, body: Vec<Doc>) -> Doc {
+    let mut line = vec![Doc::text("("), Doc::text(keyword)];
+    for operand in operands {
+        line.push(Doc::text(" "));
+        line.push(operand);
+    }
+    let mut rest = Vec::new();
+    for doc in body {
+        rest.push(Doc::Line);
+        rest.push(doc);
+    }
+    line.push(Doc::nest(2, Doc::Concat(rest)));
+    line.push(Doc::text(")"));
+    Doc::group(Doc::align(Doc::Concat(line)))
+}
+
+/// Lays out `items` between `open` and `close`, either on one line or with
+/// every item on a line of its own, aligned with the first. Items that are
+/// all atoms are instead filled into as few lines as they fit.
+pub(crate) fn list(open: &str, items: Vec<Doc>, close: &str) -> Doc {
+    let fill = items.iter().all(|item| matches!(item, Doc::Text(_)));
+    let mut inner = Vec::new();
+    for (i, item) in items.into_iter().enumerate() {
+        if i == 0 {
+            inner.push(item);
+        } else if fill {
+            inner.push(Doc::group(Doc::Concat(vec![Doc::Line, item])));
+        } else {
+            inner.push(Doc::Line);
+            inner.push(item);
+        }
+    }
+    Doc::group(Doc::align(Doc::Concat(vec![
+        Doc::text(open),
+        Doc::nest(open.chars().count(), Doc::Concat(inner)),
+        Doc::text(close),
+    ])))
+}
+
+// a document still to be laid out: its indentation, whether it is laid out
+// on one line, and the document
+type Pending<'d> = (usize, bool, &'d Doc);
+
+/// Lays out `doc` to fit `width` columns, starting in column `column`.
+pub(crate) fn render(doc: &Doc, width: usize, column: usize) -> String {
+    let mut out = String::new();
+    let mut column = column;
+    let mut stack: Vec<Pending> = vec![(0, false, doc)];
+    while let Some((indent, flat, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(text) => {
+                out.push_str(text);
+                column = match text.rfind('\n') {
+                    // a string literal spanning lines
+                    Some(i) => text[i + 1..].chars().count(),
+                    None => column + text.chars().count(),
+                };
+            }
+            Doc::Line if flat => {
+                out.push(' ');
+                column += 1;
+            }
+            Doc::Line => {
+                out.push('\n');
+                out.push_str(&" ".repeat(indent));
+                column = indent;
+            }
+            Doc::Nest(more, doc) => stack.push((indent + more, flat, doc)),
+            Doc::Align(doc) => stack.push((column, flat, doc)),
+            Doc::Group(doc) => {
+                let remaining = width as isize - column as isize;
+                let flat = flat || fits(remaining, (indent, true, doc), &stack);
+                stack.push((indent, flat, doc));
+            }
+            Doc::Concat(docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((indent, flat, doc));
+                }
+            }
+            Doc::Break => {}
+            Doc::BlankLine => {
+                out.push('\n');
+                column = 0;
+            }
+        }
+    }
+    out
+}
+
+/// Returns true if `next`, followed by the documents in `rest` up to the next
+/// line break, fits in `remaining` columns.
+fn fits(mut remaining: isize, next: Pending, rest: &[Pending]) -> bool {
+    let mut stack = vec![next];
+    let mut rest = rest.iter().rev();
+    while remaining >= 0 {
+        let (indent, flat, doc) = match stack.pop().or_else(|| rest.next().copied()) {
+            Some(pending) => pending,
+            None => return true,
+        };
+        match doc {
+            Doc::Text(text) => {
+                let line = text.split('\n').next().unwrap_or_default();
+                remaining -= line.chars().count() as isize;
+                if line.len() < text.len() {
+                    return remaining >= 0;
+                }
+            }
+            Doc::Line if flat => remaining -= 1,
+            Doc::Line | Doc::BlankLine => return true,
+            Doc::Break if flat => return false,
+            Doc::Break => {}
+            Doc::Nest(more, doc) => stack.push((indent + more, flat, doc)),
+            Doc::Align(doc) => stack.push((indent, flat, doc)),
+            Doc::Group(doc) => stack.push((indent, flat, doc)),
+            Doc::Concat(docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((indent, flat, doc));
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Shows `exp` as `write` does, broken into lines and indented to fit `width`
+/// columns when printed from column `column` on.
+pub(crate) fn pretty(exp: &Exp, width: usize, column: usize) -> String {
+    let mut labels = Labels::new(exp, Sharing::Cycles);
+    render(&doc(exp, &mut labels), width, column)
+}
+
+/// The width error messages are laid out to fit.
+const MESSAGE_WIDTH: usize = 60;
+
+/// Embeds `exp` in an error message after `prefix`. Long values are broken
+/// into lines, which are indented relative to the start of the message.
+pub(crate) fn message(prefix: &str, exp: &Exp) -> String {
+    let column = prefix.chars().count();
+    format!("{}{}", prefix, pretty(exp, MESSAGE_WIDTH, column))
+}
@@ -0,0 +1,160 @@
+//! `eval` and environments (R7RS 6.12).
+use crate::ast::*;
+use crate::primitives::*;
+use crate::runtime::*;
+use crate::symbol::Symbol;
+use crate::Exception::*;
+
+/// `(eval expr [environment])` evaluates the datum `expr` in `environment`,
+/// which defaults to the environment `eval` is called from.
+#[allow(unused_mut)]
+pub fn prim_eval(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (expr, environment) = destruct!(env, args, meta; (->Exp) (->..Exp::Environment))?;
+    match environment.as_slice() {
+        [] => eval(env, &expr),
+        [environment] => eval(&mut environment.clone(), &expr),
+        _ => Err(Exn::new(
+            meta,
+            Condition::Arity(
+                Arity::OneOf(vec![Arity::Exact(1), Arity::Exact(2)]),
+                environment.len() + 1,
+            ),
+        )),
+    }
+}
+
+/// The special forms, which is all that `null-environment` binds.
+pub(crate) const SYNTAX: [(&str, Primitive); 11] = [
+    ("define", prim_define),
+    ("lambda", prim_lambda),
+    ("case-lambda", prim_case_lambda),
+    ("define-record-type", prim_define_record_type),
+    ("if", prim_if),
+    ("cond", prim_cond),
+    ("begin", prim_begin),
+    ("or", prim_or),
+    ("and", prim_and),
+    ("quote", prim_quote),
+    ("include", prim_include),
+];
+
+/// Checks the optional version argument of `scheme-report-environment` and
+/// `null-environment`, only the versions 5 and 7 are known.
+fn check_version(meta: &Meta, version: &[f64]) -> Result<(), Exn> {
+    match version {
+        [] | [5.0] | [7.0] => Ok(()),
+        [v] => Err(Exn::other(
+            meta.clone(),
+            &format!("unsupported scheme report version: {}", v),
+        )),
+        _ => Err(Exn::new(
+            meta.clone(),
+            Condition::Arity(
+                Arity::OneOf(vec![Arity::Exact(0), Arity::Exact(1)]),
+                version.len(),
+            ),
+        )),
+    }
+}
+
+/// `(environment import-set ...)` returns a new environment with the bindings
+/// of the import sets.
+#[allow(unused_mut)]
+pub fn prim_environment(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let sets = destruct!(env, args, meta; (->..Exp))?;
+    let environment = Env::new(None);
+    for set in sets {
+        for (name, item) in import_set(&Item::new(meta.clone(), set))? {
+            environment.set(name, item);
+        }
+    }
+    Ok(Item::new(meta, Exp::Environment(environment)))
+}
+
+/// `(scheme-report-environment [version])` returns a new environment with
+/// every built-in procedure, like the one programs start in.
+#[allow(unused_mut)]
+pub fn prim_scheme_report_environment(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let version = destruct!(env, args, meta; (->..Exp::Number))?;
+    check_version(&meta, &version)?;
+    Ok(Item::new(meta, Exp::Environment(default_env())))
+}
+
+/// `(null-environment [version])` returns a new environment with only the
+/// special forms, like `define`, `lambda` and `if`.
+#[allow(unused_mut)]
+pub fn prim_null_environment(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let version = destruct!(env, args, meta; (->..Exp::Number))?;
+    check_version(&meta, &version)?;
+    let environment = Env::new(None);
+    for (name, fun) in SYNTAX.iter() {
+        environment.set_prim(name, *fun);
+    }
+    Ok(Item::new(meta, Exp::Environment(environment)))
+}
+
+/// `(interaction-environment)` returns the global environment of the running
+/// program.
+#[allow(unused_mut)]
+pub fn prim_interaction_environment(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    destruct!(env, args, meta;)?;
+    Ok(Item::new(meta, Exp::Environment(env.root())))
+}
+
+/// `(the-environment)` returns the environment it is evaluated in, including
+/// the local bindings of the procedures it is called from.
+#[allow(unused_mut)]
+pub fn prim_the_environment(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    destruct!(env, args, meta;)?;
+    Ok(Item::new(meta, Exp::Environment(env.clone())))
+}
+
+#[allow(unused_mut)]
+pub fn prim_is_environment(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let x = destruct!(env, args, meta; (->Exp))?;
+    Ok(Item::new(
+        meta,
+        Exp::Boolean(matches!(x.exp, Exp::Environment(_))),
+    ))
+}
+
+#[allow(unused_mut)]
+pub fn prim_environment_is_bound(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (environment, name) = destruct!(env, args, meta; (->Exp::Environment) (->Exp::Symbol))?;
+    Ok(Item::new(
+        meta,
+        Exp::Boolean(environment.get(name).is_some()),
+    ))
+}
+
+/// `(environment-ref environment symbol)` fails if `symbol` is unbound.
+#[allow(unused_mut)]
+pub fn prim_environment_ref(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (environment, name) = destruct!(env, args, meta; (->Exp::Environment) (->Exp::Symbol))?;
+    environment
+        .get(name)
+        .ok_or_else(|| Exn::other(meta, &format!("{} is not bound in the environment", name)))
+}
+
+/// `(environment-define! environment symbol value)` binds `symbol` in the
+/// innermost scope of `environment`.
+#[allow(unused_mut)]
+pub fn prim_environment_define(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (environment, name, value) =
+        destruct!(env, args, meta; (->Exp::Environment) (->Exp::Symbol) (->Exp))?;
+    environment.set(name, value);
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+/// `(environment-bound-names environment)` returns the sorted list of all
+/// symbols bound in `environment`.
+#[allow(unused_mut)]
+pub fn prim_environment_bound_names(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let environment = destruct!(env, args, meta; (->Exp::Environment))?;
+    let names = environment
+        .names()
+        .into_iter()
+        .map(|name: Symbol| Item::new(meta.clone(), Exp::Symbol(name)))
+        .collect();
+    Ok(Item::list(meta, names))
+}
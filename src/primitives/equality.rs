@@ -0,0 +1,122 @@
+//! Equivalence predicates and the list searches built on them (R7RS 6.1, 6.4).
+use crate::ast::*;
+use crate::runtime::*;
+use crate::Exception::*;
+
+#[allow(unused_mut)]
+pub fn prim_eq(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (a, b) = destruct!(env, args, meta; (->Exp) (->Exp))?;
+    Ok(Item::new(meta, Exp::Boolean(a.exp.is_eq(&b.exp))))
+}
+
+#[allow(unused_mut)]
+pub fn prim_eqv(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (a, b) = destruct!(env, args, meta; (->Exp) (->Exp))?;
+    Ok(Item::new(meta, Exp::Boolean(a.exp.is_eqv(&b.exp))))
+}
+
+#[allow(unused_mut)]
+pub fn prim_equal(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (a, b) = destruct!(env, args, meta; (->Exp) (->Exp))?;
+    Ok(Item::new(meta, Exp::Boolean(a.exp.is_equal(&b.exp))))
+}
+
+/// Returns the first sublist of `list` whose car satisfies `matches`, or `#f`.
+fn mem(list: Item, mut matches: impl FnMut(&Item) -> Result<bool, Exn>) -> Result<Exp, Exn> {
+    // searching a circular list for something not in it would never end
+    if list.exp.is_circular() {
+        return Err(Exn::typ(list.meta, "list", &list.exp.type_name()));
+    }
+    let mut rest = list.exp;
+    while let Exp::Pair(cell) = rest {
+        if matches(&cell.car())? {
+            return Ok(Exp::Pair(cell));
+        }
+        rest = cell.cdr().exp;
+    }
+    Ok(Exp::Boolean(false))
+}
+
+/// Returns the first pair in the association list `alist` whose car satisfies
+/// `matches`, or `#f`.
+fn ass(alist: Item, mut matches: impl FnMut(&Item) -> Result<bool, Exn>) -> Result<Exp, Exn> {
+    if alist.exp.is_circular() {
+        return Err(Exn::typ(alist.meta, "list", &alist.exp.type_name()));
+    }
+    let mut rest = alist.exp;
+    while let Exp::Pair(cell) = rest {
+        let entry = cell.car();
+        match entry.exp {
+            Exp::Pair(ref pair) => {
+                if matches(&pair.car())? {
+                    return Ok(entry.exp);
+                }
+            }
+            ref x => return Err(Exn::typ(entry.meta.clone(), "pair", &x.type_name())),
+        }
+        rest = cell.cdr().exp;
+    }
+    Ok(Exp::Boolean(false))
+}
+
+/// Calls the user supplied comparison procedure of `member` and `assoc`.
+fn compare(env: &mut Env, meta: &Meta, proc: &Item, a: &Item, b: &Item) -> Result<bool, Exn> {
+    let res = apply(env, meta.clone(), proc.clone(), vec![a.clone(), b.clone()])?;
+    Ok(!matches!(res.exp, Exp::Boolean(false)))
+}
+
+#[allow(unused_mut)]
+pub fn prim_memq(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (obj, list) = destruct!(env, args, meta; (->Exp) (->Exp))?;
+    let res = mem(list, |x| Ok(obj.exp.is_eq(&x.exp)))?;
+    Ok(Item::new(meta, res))
+}
+
+#[allow(unused_mut)]
+pub fn prim_memv(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (obj, list) = destruct!(env, args, meta; (->Exp) (->Exp))?;
+    let res = mem(list, |x| Ok(obj.exp.is_eqv(&x.exp)))?;
+    Ok(Item::new(meta, res))
+}
+
+/// `(member obj list [compare])` uses `equal?` unless a procedure is given.
+pub fn prim_member(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (obj, list, compare_proc) = destruct!(env, args, meta; (->Exp) (->Exp) (->..Exp))?;
+    let res = match compare_proc.len() {
+        0 => mem(list, |x| Ok(obj.exp.is_equal(&x.exp)))?,
+        1 => {
+            let proc = Item::new(meta.clone(), compare_proc[0].clone());
+            mem(list, |x| compare(env, &meta, &proc, &obj, x))?
+        }
+        n => return Err(Exn::arity(meta, 3, n + 2)),
+    };
+    Ok(Item::new(meta, res))
+}
+
+#[allow(unused_mut)]
+pub fn prim_assq(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (obj, alist) = destruct!(env, args, meta; (->Exp) (->Exp))?;
+    let res = ass(alist, |x| Ok(obj.exp.is_eq(&x.exp)))?;
+    Ok(Item::new(meta, res))
+}
+
+#[allow(unused_mut)]
+pub fn prim_assv(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (obj, alist) = destruct!(env, args, meta; (->Exp) (->Exp))?;
+    let res = ass(alist, |x| Ok(obj.exp.is_eqv(&x.exp)))?;
+    Ok(Item::new(meta, res))
+}
+
+/// `(assoc obj alist [compare])` uses `equal?` unless a procedure is given.
+pub fn prim_assoc(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (obj, alist, compare_proc) = destruct!(env, args, meta; (->Exp) (->Exp) (->..Exp))?;
+    let res = match compare_proc.len() {
+        0 => ass(alist, |x| Ok(obj.exp.is_equal(&x.exp)))?,
+        1 => {
+            let proc = Item::new(meta.clone(), compare_proc[0].clone());
+            ass(alist, |x| compare(env, &meta, &proc, &obj, x))?
+        }
+        n => return Err(Exn::arity(meta, 3, n + 2)),
+    };
+    Ok(Item::new(meta, res))
+}
@@ -0,0 +1,50 @@
+//! Sourcing other files with `load` and `include` (R7RS 4.1.7, 6.14).
+use crate::ast::*;
+use crate::run::{exec_file, parse_file};
+use crate::runtime::*;
+use crate::Exception::*;
+use std::path::Path;
+
+/// Resolves `path` relative to the directory of the file `meta` points into.
+/// Code that doesn't come from a file resolves paths relative to the current
+/// directory.
+fn resolve(meta: &Meta, path: &str) -> String {
+    match &meta.file_name {
+        Some(file) if Path::new(path).is_relative() => match Path::new(&**file).parent() {
+            Some(dir) => dir.join(path).to_string_lossy().into_owned(),
+            None => path.to_string(),
+        },
+        _ => path.to_string(),
+    }
+}
+
+/// `(load "file")` evaluates the file into the current environment.
+#[allow(unused_mut)]
+pub fn prim_load(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let path = destruct!(env, args, meta; (->Exp::String))?;
+    exec_file(env, &meta, &resolve(&meta, &path))?;
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+/// Evaluates the forms of the files named by the string literals `files` as if
+/// they appeared in their place, and returns the value of the last one.
+pub(crate) fn include(env: &mut Env, meta: Meta, files: Vec<Item>) -> Result<Item, Exn> {
+    let mut result = Item::new(meta, Exp::Unspecified);
+    for file in files {
+        let path = match &file.exp {
+            Exp::String(path) => resolve(&file.meta, path),
+            x => return Err(Exn::typ(file.meta.clone(), "string", &x.type_name())),
+        };
+        for form in parse_file(&file.meta, &path)? {
+            result = eval(env, &form)?;
+        }
+    }
+    Ok(result)
+}
+
+/// `(include "file" ...)` splices the forms of the files into the program.
+#[allow(unused_mut)]
+pub fn prim_include(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let files = destruct!(env, args, meta; (Item) (..Item))?;
+    include(env, meta, std::iter::once(files.0).chain(files.1).collect())
+}
@@ -0,0 +1,210 @@
+//! Hash tables (SRFI-69).
+use crate::ast::*;
+use crate::gc::Gc;
+use crate::pretty::message;
+use crate::primitives::*;
+use crate::runtime::*;
+use crate::table::{Equivalence, HashTable};
+use crate::Exception::*;
+
+/// Checks that `key` can be stored in `table`, string tables only take strings.
+fn check_key(table: &HashTable, key: &Item) -> Result<(), Exn> {
+    match (table.equivalence, &key.exp) {
+        (Equivalence::String, Exp::String(_)) => Ok(()),
+        (Equivalence::String, x) => Err(Exn::typ(key.meta.clone(), "string", &x.type_name())),
+        _ => Ok(()),
+    }
+}
+
+fn is_prim(p: Primitive, fun: Primitive) -> bool {
+    p as usize == fun as usize
+}
+
+fn call(env: &mut Env, meta: &Meta, proc: Exp, args: Vec<Item>) -> Result<Item, Exn> {
+    apply(env, meta.clone(), Item::new(meta.clone(), proc), args)
+}
+
+/// `(make-hash-table [equiv])` where `equiv` is one of `eq?`, `eqv?`, `equal?`
+/// and `string=?`. Tables compare their keys with `equal?` by default.
+#[allow(unused_mut)]
+pub fn prim_make_hash_table(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let equiv = destruct!(env, args, meta; (->..Exp))?;
+    let equivalence = match equiv.as_slice() {
+        [] => Equivalence::Equal,
+        [Exp::Primitive(p)] if is_prim(*p, prim_eq) => Equivalence::Eq,
+        [Exp::Primitive(p)] if is_prim(*p, prim_eqv) => Equivalence::Eqv,
+        [Exp::Primitive(p)] if is_prim(*p, prim_equal) => Equivalence::Equal,
+        [Exp::Primitive(p)] if is_prim(*p, prim_string_eq) => Equivalence::String,
+        [x] => {
+            return Err(Exn::typ(
+                meta,
+                "one of eq?, eqv?, equal? or string=?",
+                &x.type_name(),
+            ))
+        }
+        x => return Err(Exn::arity(meta, 1, x.len())),
+    };
+    Ok(Item::new(
+        meta,
+        Exp::HashTable(Gc::new(HashTable::new(equivalence))),
+    ))
+}
+
+#[allow(unused_mut)]
+pub fn prim_is_hash_table(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let x = destruct!(env, args, meta; (->Exp))?;
+    Ok(Item::new(
+        meta,
+        Exp::Boolean(matches!(x.exp, Exp::HashTable(_))),
+    ))
+}
+
+/// `(hash-table-ref table key [thunk])` calls `thunk` if `key` is missing and
+/// fails without one.
+#[allow(unused_mut)]
+pub fn prim_hash_table_ref(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (table, key, thunk) = destruct!(env, args, meta; (->Exp::HashTable) (->Exp) (->..Exp))?;
+    check_key(&table, &key)?;
+    if thunk.len() > 1 {
+        return Err(Exn::arity(meta, 3, thunk.len() + 2));
+    }
+    match (table.get(&key.exp), thunk.into_iter().next()) {
+        (Some(val), _) => Ok(val),
+        (None, Some(thunk)) => call(env, &meta, thunk, Vec::new()),
+        (None, None) => Err(Exn::other(meta, &message("key not found: ", &key.exp))),
+    }
+}
+
+#[allow(unused_mut)]
+pub fn prim_hash_table_ref_default(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (table, key, default) = destruct!(env, args, meta; (->Exp::HashTable) (->Exp) (->Exp))?;
+    check_key(&table, &key)?;
+    Ok(table.get(&key.exp).unwrap_or(default))
+}
+
+#[allow(unused_mut)]
+pub fn prim_hash_table_set(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (table, key, val) = destruct!(env, args, meta; (->Exp::HashTable) (->Exp) (->Exp))?;
+    check_key(&table, &key)?;
+    table.set(key, val);
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+#[allow(unused_mut)]
+pub fn prim_hash_table_delete(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (table, key) = destruct!(env, args, meta; (->Exp::HashTable) (->Exp))?;
+    check_key(&table, &key)?;
+    table.delete(&key.exp);
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+#[allow(unused_mut)]
+pub fn prim_hash_table_contains(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (table, key) = destruct!(env, args, meta; (->Exp::HashTable) (->Exp))?;
+    check_key(&table, &key)?;
+    Ok(Item::new(meta, Exp::Boolean(table.contains(&key.exp))))
+}
+
+/// `(hash-table-update! table key proc [thunk])` stores the result of calling
+/// `proc` on the current value, which comes from `thunk` if `key` is missing.
+#[allow(unused_mut)]
+pub fn prim_hash_table_update(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (table, key, proc, thunk) =
+        destruct!(env, args, meta; (->Exp::HashTable) (->Exp) (->Exp) (->..Exp))?;
+    check_key(&table, &key)?;
+    if thunk.len() > 1 {
+        return Err(Exn::arity(meta, 4, thunk.len() + 3));
+    }
+    let val = match (table.get(&key.exp), thunk.into_iter().next()) {
+        (Some(val), _) => val,
+        (None, Some(thunk)) => call(env, &meta, thunk, Vec::new())?,
+        (None, None) => return Err(Exn::other(meta, &message("key not found: ", &key.exp))),
+    };
+    let val = apply(env, meta.clone(), proc, vec![val])?;
+    table.set(key, val);
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+#[allow(unused_mut)]
+pub fn prim_hash_table_update_default(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (table, key, proc, default) =
+        destruct!(env, args, meta; (->Exp::HashTable) (->Exp) (->Exp) (->Exp))?;
+    check_key(&table, &key)?;
+    let val = table.get(&key.exp).unwrap_or(default);
+    let val = apply(env, meta.clone(), proc, vec![val])?;
+    table.set(key, val);
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+#[allow(unused_mut)]
+pub fn prim_hash_table_size(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let table = destruct!(env, args, meta; (->Exp::HashTable))?;
+    Ok(Item::new(meta, Exp::Number(table.len() as f64)))
+}
+
+#[allow(unused_mut)]
+pub fn prim_hash_table_keys(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let table = destruct!(env, args, meta; (->Exp::HashTable))?;
+    let keys = table.entries().into_iter().map(|(key, _)| key).collect();
+    Ok(Item::list(meta, keys))
+}
+
+#[allow(unused_mut)]
+pub fn prim_hash_table_values(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let table = destruct!(env, args, meta; (->Exp::HashTable))?;
+    let values = table.entries().into_iter().map(|(_, val)| val).collect();
+    Ok(Item::list(meta, values))
+}
+
+#[allow(unused_mut)]
+pub fn prim_hash_table_to_alist(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let table = destruct!(env, args, meta; (->Exp::HashTable))?;
+    let pairs = table
+        .entries()
+        .into_iter()
+        .map(|(key, val)| Item::cons(meta.clone(), key, val))
+        .collect();
+    Ok(Item::list(meta, pairs))
+}
+
+/// `(alist->hash-table alist [equiv])`, earlier entries win over later ones
+/// with the same key, just like with `assoc`.
+#[allow(unused_mut)]
+pub fn prim_alist_to_hash_table(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (alist, equiv) = destruct!(env, args, meta; (->Exp) (..Item))?;
+    let table = prim_make_hash_table(env, meta.clone(), Item::list(meta.clone(), equiv))?;
+    let hash_table = match &table.exp {
+        Exp::HashTable(t) => t,
+        _ => unreachable!(),
+    };
+    if !alist.exp.is_list() {
+        return Err(Exn::typ(alist.meta, "list", &alist.exp.type_name()));
+    }
+    let mut rest = alist.exp;
+    while let Exp::Pair(cell) = rest {
+        let entry = cell.car();
+        match &entry.exp {
+            Exp::Pair(pair) => {
+                let key = pair.car();
+                check_key(hash_table, &key)?;
+                if !hash_table.contains(&key.exp) {
+                    hash_table.set(key, pair.cdr());
+                }
+            }
+            x => return Err(Exn::typ(entry.meta.clone(), "pair", &x.type_name())),
+        }
+        rest = cell.cdr().exp;
+    }
+    Ok(table)
+}
+
+/// `(hash-table-walk table proc)` calls `proc` with each key and value. Changes
+/// to the table made by `proc` don't affect which entries are visited.
+#[allow(unused_mut)]
+pub fn prim_hash_table_walk(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (table, proc) = destruct!(env, args, meta; (->Exp::HashTable) (->Exp))?;
+    for (key, val) in table.entries() {
+        apply(env, meta.clone(), proc.clone(), vec![key, val])?;
+    }
+    Ok(Item::new(meta, Exp::Unspecified))
+}
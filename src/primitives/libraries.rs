@@ -0,0 +1,178 @@
+//! `define-library` and `import` (R7RS 5.2, 5.6).
+use crate::ast::*;
+use crate::library::{self, Library};
+use crate::primitives::include;
+use crate::runtime::*;
+use crate::symbol::Symbol;
+use crate::Exception::*;
+
+fn symbol(item: &Item) -> Result<Symbol, Exn> {
+    match item.exp {
+        Exp::Symbol(s) => Ok(s),
+        ref x => Err(Exn::typ(item.meta.clone(), "symbol", &x.type_name())),
+    }
+}
+
+/// Returns the elements of the list `item`.
+fn elements(item: &Item) -> Result<Vec<Item>, Exn> {
+    match &item.exp {
+        Exp::Nil => Ok(Vec::new()),
+        Exp::Pair(cell) if item.exp.is_list() => Ok(cell.clone().into_iter().collect()),
+        x => Err(Exn::typ(item.meta.clone(), "list", &x.type_name())),
+    }
+}
+
+fn not_exported(item: &Item, name: Symbol) -> Exn {
+    Exn::other(item.meta.clone(), &format!("{} is not exported", name))
+}
+
+/// Resolves an import set like `(prefix (only (scheme base) car cdr) base:)`
+/// into the bindings it imports.
+pub(crate) fn import_set(set: &Item) -> Result<Vec<(Symbol, Item)>, Exn> {
+    if let Exp::Pair(cell) = &set.exp {
+        // library names only contain symbols and numbers, so a list as second
+        // element means this is `only`, `except`, `prefix` or `rename`
+        if let (Exp::Symbol(modifier), Exp::Pair(rest)) = (cell.car().exp, cell.cdr().exp) {
+            if let Exp::Pair(_) = rest.car().exp {
+                let mut bindings = import_set(&rest.car())?;
+                let args = elements(&rest.cdr())?;
+                return match &*modifier.name() {
+                    "only" => {
+                        let mut only = Vec::new();
+                        for arg in args {
+                            let name = symbol(&arg)?;
+                            match bindings.iter().find(|(s, _)| *s == name) {
+                                Some(binding) => only.push(binding.clone()),
+                                None => return Err(not_exported(&arg, name)),
+                            }
+                        }
+                        Ok(only)
+                    }
+                    "except" => {
+                        for arg in args {
+                            let name = symbol(&arg)?;
+                            match bindings.iter().position(|(s, _)| *s == name) {
+                                Some(i) => drop(bindings.remove(i)),
+                                None => return Err(not_exported(&arg, name)),
+                            }
+                        }
+                        Ok(bindings)
+                    }
+                    "prefix" => {
+                        if args.len() != 1 {
+                            return Err(Exn::arity(set.meta.clone(), 2, args.len() + 1));
+                        }
+                        let prefix = symbol(&args[0])?.name();
+                        Ok(bindings
+                            .into_iter()
+                            .map(|(s, item)| (Symbol::intern(&format!("{}{}", prefix, s)), item))
+                            .collect())
+                    }
+                    "rename" => {
+                        for arg in args {
+                            let pair = elements(&arg)?;
+                            if pair.len() != 2 {
+                                return Err(Exn::arity(arg.meta, 2, pair.len()));
+                            }
+                            let (from, to) = (symbol(&pair[0])?, symbol(&pair[1])?);
+                            match bindings.iter_mut().find(|(s, _)| *s == from) {
+                                Some(binding) => binding.0 = to,
+                                None => return Err(not_exported(&arg, from)),
+                            }
+                        }
+                        Ok(bindings)
+                    }
+                    _ => Err(Exn::syntax(
+                        set.meta.clone(),
+                        "Expected only, except, prefix or rename",
+                    )),
+                };
+            }
+        }
+    }
+    Ok(library::find(set)?.exports.clone())
+}
+
+/// `(import import-set ...)` binds the identifiers exported by libraries in
+/// the current environment.
+#[allow(unused_mut)]
+pub fn prim_import(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let sets = destruct!(env, args, meta; (..Item))?;
+    for set in sets {
+        for (name, item) in import_set(&set)? {
+            env.set(name, item);
+        }
+    }
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+/// `(define-library name declaration ...)` where the declarations are
+/// `(export spec ...)`, `(import import-set ...)`, `(begin expr ...)` and
+/// `(include "file" ...)`.
+/// The body of a library only sees what it imports.
+#[allow(unused_mut)]
+pub fn prim_define_library(_: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (name, declarations) = destruct!(env, args, meta; (Item) (..Item))?;
+    let name = library::name_parts(&name)?;
+    let mut lib_env = Env::new(None);
+    let mut specs = Vec::new();
+    for declaration in declarations {
+        let (kind, body) = destruct!(env, declaration, meta; (Exp::Symbol) (..Item))?;
+        match &*kind.name() {
+            "export" => specs.extend(body),
+            "import" => {
+                for set in body {
+                    for (name, item) in import_set(&set)? {
+                        lib_env.set(name, item);
+                    }
+                }
+            }
+            "begin" => {
+                for expr in body {
+                    eval(&mut lib_env, &expr)?;
+                }
+            }
+            "include" => {
+                include(&mut lib_env, meta.clone(), body)?;
+            }
+            _ => {
+                return Err(Exn::syntax(
+                    declaration.meta,
+                    "Expected export, import, begin or include",
+                ))
+            }
+        }
+    }
+
+    let mut exports = Vec::new();
+    for spec in specs {
+        let (internal, external) = match &spec.exp {
+            Exp::Symbol(s) => (*s, *s),
+            _ => {
+                let rename = elements(&spec)?;
+                match &rename[..] {
+                    [keyword, from, to] if symbol(keyword)?.name().as_ref() == "rename" => {
+                        (symbol(from)?, symbol(to)?)
+                    }
+                    _ => {
+                        return Err(Exn::syntax(
+                            spec.meta,
+                            "Expected an identifier or (rename from to)",
+                        ))
+                    }
+                }
+            }
+        };
+        match lib_env.get(internal) {
+            Some(item) => exports.push((external, item)),
+            None => {
+                return Err(Exn::other(
+                    spec.meta,
+                    &format!("{} is exported but not defined", internal),
+                ))
+            }
+        }
+    }
+    library::register(&name, Library { exports });
+    Ok(Item::new(meta, Exp::Unspecified))
+}
@@ -0,0 +1,415 @@
+//! The list library: R7RS 6.4 and the core of SRFI-1.
+use crate::ast::*;
+use crate::gc;
+use crate::runtime::*;
+use crate::Exception::*;
+
+/// Returns the elements of the proper list `list`.
+fn items(list: &Item) -> Result<Vec<Item>, Exn> {
+    match &list.exp {
+        Exp::Nil => Ok(Vec::new()),
+        Exp::Pair(cell) if list.exp.is_list() => Ok(cell.clone().into_iter().collect()),
+        x => Err(Exn::typ(list.meta.clone(), "list", &x.type_name())),
+    }
+}
+
+/// Returns the elements of each of the lists passed to an n-ary procedure like `map`.
+fn items_of_each(meta: &Meta, first: Item, rest: Vec<Exp>) -> Result<Vec<Vec<Item>>, Exn> {
+    let mut lists = vec![items(&first)?];
+    for list in rest {
+        lists.push(items(&Item::new(meta.clone(), list))?);
+    }
+    Ok(lists)
+}
+
+/// The arguments for the `i`th call of an n-ary procedure like `map`.
+fn nth_of_each(lists: &[Vec<Item>], i: usize) -> Vec<Item> {
+    lists.iter().map(|list| list[i].clone()).collect()
+}
+
+fn shortest(lists: &[Vec<Item>]) -> usize {
+    lists.iter().map(Vec::len).min().unwrap_or(0)
+}
+
+fn is_true(item: &Item) -> bool {
+    !matches!(item.exp, Exp::Boolean(false))
+}
+
+/// Fails for a circular list, which the procedures that walk a list to its
+/// end would never finish.
+fn not_circular(list: &Item) -> Result<(), Exn> {
+    if list.exp.is_circular() {
+        return Err(Exn::typ(list.meta.clone(), "list", &list.exp.type_name()));
+    }
+    Ok(())
+}
+
+fn index(meta: &Meta, k: f64) -> Result<usize, Exn> {
+    if k >= 0.0 && k.fract() == 0.0 {
+        Ok(k as usize)
+    } else {
+        Err(Exn::typ(
+            meta.clone(),
+            "non-negative integer",
+            &k.to_string(),
+        ))
+    }
+}
+
+#[allow(unused_mut)]
+pub fn prim_length(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let list = destruct!(env, args, meta; (->Exp))?;
+    if !list.exp.is_list() {
+        return Err(Exn::typ(list.meta, "list", &list.exp.type_name()));
+    }
+    let mut length = 0;
+    let mut rest = list.exp;
+    while let Exp::Pair(cell) = rest {
+        length += 1;
+        rest = cell.cdr().exp;
+    }
+    Ok(Item::new(meta, Exp::Number(length as f64)))
+}
+
+/// `(append list ...)` copies all lists but the last, which is shared with the
+/// result and may be any value.
+#[allow(unused_mut)]
+pub fn prim_append(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let mut lists = destruct!(env, args, meta; (->..Exp))?;
+    let mut result = match lists.pop() {
+        Some(last) => Item::new(meta.clone(), last),
+        None => return Ok(Item::new(meta, Exp::Nil)),
+    };
+    for list in lists.into_iter().rev() {
+        for item in items(&Item::new(meta.clone(), list))?.into_iter().rev() {
+            result = Item::cons(meta.clone(), item, result);
+        }
+    }
+    Ok(Item::new(meta, result.exp))
+}
+
+#[allow(unused_mut)]
+pub fn prim_reverse(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let list = destruct!(env, args, meta; (->Exp))?;
+    let mut result = Item::new(meta.clone(), Exp::Nil);
+    for item in items(&list)? {
+        result = Item::cons(meta.clone(), item, result);
+    }
+    Ok(Item::new(meta, result.exp))
+}
+
+/// Follows `k` cdrs of `list`, like `list-tail` and `drop`.
+fn tail(meta: &Meta, list: Item, k: f64) -> Result<Item, Exn> {
+    not_circular(&list)?;
+    let mut rest = list;
+    for _ in 0..index(meta, k)? {
+        rest = match rest.exp {
+            Exp::Pair(cell) => cell.cdr(),
+            x => return Err(Exn::typ(rest.meta, "pair", &x.type_name())),
+        };
+    }
+    Ok(rest)
+}
+
+#[allow(unused_mut)]
+pub fn prim_list_tail(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (list, k) = destruct!(env, args, meta; (->Exp) (->Exp::Number))?;
+    let rest = tail(&meta, list, k)?;
+    Ok(Item::new(meta, rest.exp))
+}
+
+#[allow(unused_mut)]
+pub fn prim_list_ref(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (list, k) = destruct!(env, args, meta; (->Exp) (->Exp::Number))?;
+    match tail(&meta, list, k)? {
+        Item {
+            exp: Exp::Pair(cell),
+            ..
+        } => Ok(Item::new(meta, cell.car().exp)),
+        x => Err(Exn::typ(meta, "pair", &x.exp.type_name())),
+    }
+}
+
+/// `(list-copy obj)` copies the pairs of `obj`, keeping an improper tail.
+#[allow(unused_mut)]
+pub fn prim_list_copy(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let list = destruct!(env, args, meta; (->Exp))?;
+    not_circular(&list)?;
+    let mut cars = Vec::new();
+    let mut rest = list.exp;
+    while let Exp::Pair(cell) = rest {
+        cars.push(cell.car());
+        rest = cell.cdr().exp;
+    }
+    gc::reserve(
+        &meta,
+        cars.len().saturating_mul(std::mem::size_of::<LispCell>()),
+    )?;
+    let mut result = Item::new(meta.clone(), rest);
+    for car in cars.into_iter().rev() {
+        result = Item::cons(meta.clone(), car, result);
+    }
+    Ok(Item::new(meta, result.exp))
+}
+
+/// `(map proc list ...)` stops at the end of the shortest list.
+pub fn prim_map(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (proc, first, rest) = destruct!(env, args, meta; (->Exp) (->Exp) (->..Exp))?;
+    let lists = items_of_each(&meta, first, rest)?;
+    let mut results = Vec::new();
+    for i in 0..shortest(&lists) {
+        results.push(apply(
+            env,
+            meta.clone(),
+            proc.clone(),
+            nth_of_each(&lists, i),
+        )?);
+    }
+    Ok(Item::list(meta, results))
+}
+
+pub fn prim_for_each(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (proc, first, rest) = destruct!(env, args, meta; (->Exp) (->Exp) (->..Exp))?;
+    let lists = items_of_each(&meta, first, rest)?;
+    for i in 0..shortest(&lists) {
+        apply(env, meta.clone(), proc.clone(), nth_of_each(&lists, i))?;
+    }
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+/// Keeps the elements of `list` for which `pred` returns `keep`.
+fn filter(env: &mut Env, meta: Meta, pred: Item, list: Item, keep: bool) -> Result<Item, Exn> {
+    let mut results = Vec::new();
+    for item in items(&list)? {
+        if is_true(&apply(env, meta.clone(), pred.clone(), vec![item.clone()])?) == keep {
+            results.push(item);
+        }
+    }
+    Ok(Item::list(meta, results))
+}
+
+#[allow(unused_mut)]
+pub fn prim_filter(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (pred, list) = destruct!(env, args, meta; (->Exp) (->Exp))?;
+    filter(env, meta, pred, list, true)
+}
+
+#[allow(unused_mut)]
+pub fn prim_remove(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (pred, list) = destruct!(env, args, meta; (->Exp) (->Exp))?;
+    filter(env, meta, pred, list, false)
+}
+
+/// `(reduce f ridentity list)` folds `list` with `(f elem acc)`, starting with
+/// its first element. Returns `ridentity` for the empty list.
+#[allow(unused_mut)]
+pub fn prim_reduce(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (f, ridentity, list) = destruct!(env, args, meta; (->Exp) (->Exp) (->Exp))?;
+    let mut items = items(&list)?.into_iter();
+    let mut acc = match items.next() {
+        Some(first) => first,
+        None => return Ok(Item::new(meta, ridentity.exp)),
+    };
+    for item in items {
+        acc = apply(env, meta.clone(), f.clone(), vec![item, acc])?;
+    }
+    Ok(Item::new(meta, acc.exp))
+}
+
+/// `(fold-left f init list ...)` computes `(f (f init e1) e2)` and so on.
+pub fn prim_fold_left(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (f, init, first, rest) = destruct!(env, args, meta; (->Exp) (->Exp) (->Exp) (->..Exp))?;
+    let lists = items_of_each(&meta, first, rest)?;
+    let mut acc = init;
+    for i in 0..shortest(&lists) {
+        let mut args = vec![acc];
+        args.append(&mut nth_of_each(&lists, i));
+        acc = apply(env, meta.clone(), f.clone(), args)?;
+    }
+    Ok(Item::new(meta, acc.exp))
+}
+
+/// `(fold-right f init list ...)` computes `(f e1 (f e2 init))` and so on.
+pub fn prim_fold_right(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (f, init, first, rest) = destruct!(env, args, meta; (->Exp) (->Exp) (->Exp) (->..Exp))?;
+    let lists = items_of_each(&meta, first, rest)?;
+    let mut acc = init;
+    for i in (0..shortest(&lists)).rev() {
+        let mut args = nth_of_each(&lists, i);
+        args.push(acc);
+        acc = apply(env, meta.clone(), f.clone(), args)?;
+    }
+    Ok(Item::new(meta, acc.exp))
+}
+
+/// `(last pair)` returns the last element of a non-empty list.
+#[allow(unused_mut)]
+pub fn prim_last(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let pair = destruct!(env, args, meta; (->Exp::Pair))?;
+    not_circular(&Item::new(meta.clone(), Exp::Pair(pair.clone())))?;
+    let mut cell = pair;
+    while let Exp::Pair(next) = cell.cdr().exp {
+        cell = next;
+    }
+    Ok(Item::new(meta, cell.car().exp))
+}
+
+/// `(take list k)` returns a new list of the first `k` elements of `list`.
+#[allow(unused_mut)]
+pub fn prim_take(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (list, k) = destruct!(env, args, meta; (->Exp) (->Exp::Number))?;
+    not_circular(&list)?;
+    let mut taken = Vec::new();
+    let mut rest = list;
+    for _ in 0..index(&meta, k)? {
+        rest = match rest.exp {
+            Exp::Pair(cell) => {
+                taken.push(cell.car());
+                cell.cdr()
+            }
+            x => return Err(Exn::typ(rest.meta, "pair", &x.type_name())),
+        };
+    }
+    gc::reserve(
+        &meta,
+        taken.len().saturating_mul(std::mem::size_of::<LispCell>()),
+    )?;
+    Ok(Item::list(meta, taken))
+}
+
+/// `(drop list k)` returns `list` without its first `k` elements, sharing the rest.
+#[allow(unused_mut)]
+pub fn prim_drop(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (list, k) = destruct!(env, args, meta; (->Exp) (->Exp::Number))?;
+    let rest = tail(&meta, list, k)?;
+    Ok(Item::new(meta, rest.exp))
+}
+
+/// `(any pred list ...)` returns the first true result of `pred`, or `#f`.
+pub fn prim_any(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (pred, first, rest) = destruct!(env, args, meta; (->Exp) (->Exp) (->..Exp))?;
+    let lists = items_of_each(&meta, first, rest)?;
+    for i in 0..shortest(&lists) {
+        let res = apply(env, meta.clone(), pred.clone(), nth_of_each(&lists, i))?;
+        if is_true(&res) {
+            return Ok(Item::new(meta, res.exp));
+        }
+    }
+    Ok(Item::new(meta, Exp::Boolean(false)))
+}
+
+/// `(every pred list ...)` returns `#f` as soon as `pred` does, otherwise its
+/// last result, or `#t` if the lists are empty.
+pub fn prim_every(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (pred, first, rest) = destruct!(env, args, meta; (->Exp) (->Exp) (->..Exp))?;
+    let lists = items_of_each(&meta, first, rest)?;
+    let mut last = Exp::Boolean(true);
+    for i in 0..shortest(&lists) {
+        let res = apply(env, meta.clone(), pred.clone(), nth_of_each(&lists, i))?;
+        if !is_true(&res) {
+            return Ok(Item::new(meta, res.exp));
+        }
+        last = res.exp;
+    }
+    Ok(Item::new(meta, last))
+}
+
+/// `(find pred list)` returns the first element satisfying `pred`, or `#f`.
+#[allow(unused_mut)]
+pub fn prim_find(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (pred, list) = destruct!(env, args, meta; (->Exp) (->Exp))?;
+    for item in items(&list)? {
+        if is_true(&apply(env, meta.clone(), pred.clone(), vec![item.clone()])?) {
+            return Ok(Item::new(meta, item.exp));
+        }
+    }
+    Ok(Item::new(meta, Exp::Boolean(false)))
+}
+
+/// `(delete x list [=])` removes all elements `equal?` to `x`, or those for
+/// which `(= x elem)` is true.
+pub fn prim_delete(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (x, list, eq) = destruct!(env, args, meta; (->Exp) (->Exp) (->..Exp))?;
+    let eq = match eq.len() {
+        0 => None,
+        1 => Some(Item::new(meta.clone(), eq[0].clone())),
+        n => return Err(Exn::arity(meta, 3, n + 2)),
+    };
+    let mut results = Vec::new();
+    for item in items(&list)? {
+        let equal = match &eq {
+            Some(eq) => is_true(&apply(
+                env,
+                meta.clone(),
+                eq.clone(),
+                vec![x.clone(), item.clone()],
+            )?),
+            None => x.exp.is_equal(&item.exp),
+        };
+        if !equal {
+            results.push(item);
+        }
+    }
+    Ok(Item::list(meta, results))
+}
+
+/// `(sort list less?)` is a stable merge sort.
+#[allow(unused_mut)]
+pub fn prim_sort(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (list, less) = destruct!(env, args, meta; (->Exp) (->Exp))?;
+    let mut items = items(&list)?;
+    let mut buffer = items.clone();
+    let mut width = 1;
+    while width < items.len() {
+        for start in (0..items.len()).step_by(2 * width) {
+            let mid = (start + width).min(items.len());
+            let end = (start + 2 * width).min(items.len());
+            let (mut i, mut j) = (start, mid);
+            for slot in buffer[start..end].iter_mut() {
+                // take from the right run only if it is strictly less, for stability
+                let take_right = j < end
+                    && (i >= mid || {
+                        let args = vec![items[j].clone(), items[i].clone()];
+                        is_true(&apply(env, meta.clone(), less.clone(), args)?)
+                    });
+                if take_right {
+                    *slot = items[j].clone();
+                    j += 1;
+                } else {
+                    *slot = items[i].clone();
+                    i += 1;
+                }
+            }
+        }
+        std::mem::swap(&mut items, &mut buffer);
+        width *= 2;
+    }
+    Ok(Item::list(meta, items))
+}
+
+/// `(iota count [start [step]])` returns the list `(start start+step ...)` with
+/// `count` elements, as in SRFI-1.
+pub fn prim_iota(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (count, rest) = destruct!(env, args, meta; (->Exp::Number) (->..Exp::Number))?;
+    if rest.len() > 2 {
+        return Err(Exn::new(
+            meta,
+            Condition::Arity(
+                Arity::OneOf(vec![Arity::Exact(1), Arity::Exact(2), Arity::Exact(3)]),
+                rest.len() + 1,
+            ),
+        ));
+    }
+    let count = index(&meta, count)?;
+    let start = rest.first().copied().unwrap_or(0.0);
+    let step = rest.get(1).copied().unwrap_or(1.0);
+    gc::reserve(&meta, count.saturating_mul(std::mem::size_of::<LispCell>()))?;
+    // without a heap limit, a count too large to allocate fails here rather
+    // than aborting the process
+    let mut items = Vec::new();
+    items
+        .try_reserve_exact(count)
+        .map_err(|_| Exn::new(meta.clone(), Condition::OutOfMemory(gc::stats().limit)))?;
+    items.extend((0..count).map(|i| Item::new(meta.clone(), Exp::Number(start + i as f64 * step))));
+    Ok(Item::list(meta, items))
+}
@@ -0,0 +1,71 @@
+use crate::ast::*;
+use crate::gc;
+use crate::runtime::*;
+use crate::symbol::Symbol;
+use crate::Exception::*;
+
+/// `(gc)` runs a full collection and returns the number of freed objects.
+#[allow(unused_mut)]
+pub fn prim_gc(_: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    destruct!(env, args, meta;)?;
+    let freed = gc::collect();
+    Ok(Item::new(meta, Exp::Number(freed as f64)))
+}
+
+/// `(gc-stats)` returns an association list describing the heap. Sizes are in
+/// bytes and pause times in milliseconds.
+#[allow(unused_mut)]
+pub fn prim_gc_stats(_: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    destruct!(env, args, meta;)?;
+    let stats = gc::stats();
+    let limit = match stats.limit {
+        Some(limit) => Exp::Number(limit as f64),
+        None => Exp::Boolean(false),
+    };
+    let entries = vec![
+        ("heap-size", Exp::Number(stats.bytes as f64)),
+        ("heap-objects", Exp::Number(stats.objects as f64)),
+        ("heap-limit", limit),
+        ("allocations", Exp::Number(stats.allocations as f64)),
+        ("allocated", Exp::Number(stats.allocated as f64)),
+        ("collections", Exp::Number(stats.collections as f64)),
+        (
+            "pause-time",
+            Exp::Number(stats.total_pause.as_secs_f64() * 1000.0),
+        ),
+        (
+            "last-pause",
+            Exp::Number(stats.last_pause.as_secs_f64() * 1000.0),
+        ),
+        ("last-freed", Exp::Number(stats.last_freed as f64)),
+    ];
+    let alist = entries
+        .into_iter()
+        .map(|(key, val)| {
+            Item::cons(
+                meta.clone(),
+                Item::new(meta.clone(), Exp::Symbol(Symbol::intern(key))),
+                Item::new(meta.clone(), val),
+            )
+        })
+        .collect();
+    Ok(Item::list(meta, alist))
+}
+
+/// `(set-heap-limit! bytes)` limits the heap size, `#f` removes the limit.
+#[allow(unused_mut)]
+pub fn prim_set_heap_limit(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let limit = destruct!(env, args, meta; (->Exp))?;
+    match limit.exp {
+        Exp::Number(bytes) if bytes >= 0.0 => gc::set_heap_limit(Some(bytes as usize)),
+        Exp::Boolean(false) => gc::set_heap_limit(None),
+        x => {
+            return Err(Exn::typ(
+                limit.meta,
+                "positive number or #f",
+                &x.type_name(),
+            ))
+        }
+    }
+    Ok(Item::new(meta, Exp::Unspecified))
+}
@@ -1,8 +1,48 @@
 #[macro_use]
 mod util;
 
+#[allow(clippy::module_inception)]
 mod primitives;
 pub use primitives::*;
 
 mod math;
 pub use math::*;
+
+mod symbols;
+pub use symbols::*;
+
+mod memory;
+pub use memory::*;
+
+mod lists;
+pub use lists::*;
+
+mod equality;
+pub use equality::*;
+
+mod types;
+pub use types::*;
+
+mod strings;
+pub use strings::*;
+
+mod hashtables;
+pub use hashtables::*;
+
+mod records;
+pub use records::*;
+
+mod libraries;
+pub use libraries::*;
+
+mod files;
+pub use files::*;
+
+mod ports;
+pub use ports::*;
+
+mod reader;
+pub use reader::*;
+
+mod environments;
+pub use environments::*;
@@ -0,0 +1,298 @@
+//! Ports and the input and output procedures working on them (R7RS 6.13).
+use crate::ast::*;
+use crate::gc::Gc;
+use crate::port::{self, Port};
+use crate::pretty::pretty;
+use crate::write::{self, Sharing};
+use crate::runtime::*;
+use crate::Exception::*;
+use std::io;
+
+/// The width `pretty-print` fits its output to by default.
+const DEFAULT_WIDTH: usize = 80;
+
+fn io_error(meta: &Meta, e: io::Error) -> Exn {
+    Exn::io(meta.clone(), &e.to_string())
+}
+
+/// Returns the optional port argument of a procedure taking `fixed` other
+/// arguments, or `default` if it was left out.
+fn port_arg(
+    meta: &Meta,
+    fixed: usize,
+    port: Vec<Exp>,
+    default: fn() -> Gc<Port>,
+) -> Result<Gc<Port>, Exn> {
+    match port.as_slice() {
+        [] => Ok(default()),
+        [Exp::Port(port)] => Ok(port.clone()),
+        [x] => Err(Exn::typ(meta.clone(), "port", &x.type_name())),
+        _ => Err(Exn::new(
+            meta.clone(),
+            Condition::Arity(
+                Arity::OneOf(vec![Arity::Exact(fixed), Arity::Exact(fixed + 1)]),
+                fixed + port.len(),
+            ),
+        )),
+    }
+}
+
+fn input_port(meta: &Meta, fixed: usize, port: Vec<Exp>) -> Result<Gc<Port>, Exn> {
+    let port = port_arg(meta, fixed, port, port::current_input)?;
+    if !port.is_input {
+        return Err(Exn::typ(meta.clone(), "input port", "output port"));
+    }
+    Ok(port)
+}
+
+fn output_port(meta: &Meta, fixed: usize, port: Vec<Exp>) -> Result<Gc<Port>, Exn> {
+    let port = port_arg(meta, fixed, port, port::current_output)?;
+    if port.is_input {
+        return Err(Exn::typ(meta.clone(), "output port", "input port"));
+    }
+    Ok(port)
+}
+
+fn string_or_eof(string: Option<String>) -> Exp {
+    match string {
+        Some(string) => Exp::String(Gc::new(string)),
+        None => Exp::Eof,
+    }
+}
+
+#[allow(unused_mut)]
+pub fn prim_current_input_port(_: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    destruct!(env, args, meta;)?;
+    Ok(Item::new(meta, Exp::Port(port::current_input())))
+}
+
+#[allow(unused_mut)]
+pub fn prim_current_output_port(_: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    destruct!(env, args, meta;)?;
+    Ok(Item::new(meta, Exp::Port(port::current_output())))
+}
+
+#[allow(unused_mut)]
+pub fn prim_current_error_port(_: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    destruct!(env, args, meta;)?;
+    Ok(Item::new(meta, Exp::Port(port::current_error())))
+}
+
+#[allow(unused_mut)]
+pub fn prim_open_input_file(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let path = destruct!(env, args, meta; (->Exp::String))?;
+    let port = Port::open_input_file(&path)
+        .map_err(|e| Exn::io(meta.clone(), &format!("could not open {}: {}", *path, e)))?;
+    Ok(Item::new(meta, Exp::Port(Gc::new(port))))
+}
+
+#[allow(unused_mut)]
+pub fn prim_open_output_file(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let path = destruct!(env, args, meta; (->Exp::String))?;
+    let port = Port::open_output_file(&path)
+        .map_err(|e| Exn::io(meta.clone(), &format!("could not open {}: {}", *path, e)))?;
+    Ok(Item::new(meta, Exp::Port(Gc::new(port))))
+}
+
+#[allow(unused_mut)]
+pub fn prim_open_input_string(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let string = destruct!(env, args, meta; (->Exp::String))?;
+    Ok(Item::new(
+        meta,
+        Exp::Port(Gc::new(Port::input_string(&string))),
+    ))
+}
+
+#[allow(unused_mut)]
+pub fn prim_open_output_string(_: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    destruct!(env, args, meta;)?;
+    Ok(Item::new(meta, Exp::Port(Gc::new(Port::output_string()))))
+}
+
+/// `(get-output-string port)` returns what was written to a port made by
+/// `open-output-string`.
+#[allow(unused_mut)]
+pub fn prim_get_output_string(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let port = destruct!(env, args, meta; (->Exp::Port))?;
+    match port.contents() {
+        Some(string) => Ok(Item::new(meta, Exp::String(Gc::new(string)))),
+        None => Err(Exn::typ(meta, "string output port", &port.name)),
+    }
+}
+
+/// `(call-with-output-file path proc)` calls `proc` with a port writing to the
+/// file, which is closed once `proc` returns.
+#[allow(unused_mut)]
+pub fn prim_call_with_output_file(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (path, proc) = destruct!(env, args, meta; (->Exp::String) (->Exp))?;
+    let port = Port::open_output_file(&path)
+        .map_err(|e| Exn::io(meta.clone(), &format!("could not open {}: {}", *path, e)))?;
+    let port = Gc::new(port);
+    let port_item = Item::new(meta.clone(), Exp::Port(port.clone()));
+    let result = apply(env, meta.clone(), proc, vec![port_item]);
+    port.close().map_err(|e| io_error(&meta, e))?;
+    result
+}
+
+/// `(with-output-to-string thunk)` returns everything `thunk` writes to the
+/// current output port as a string.
+#[allow(unused_mut)]
+pub fn prim_with_output_to_string(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let thunk = destruct!(env, args, meta; (->Exp))?;
+    let port = Gc::new(Port::output_string());
+    let previous = port::replace_current_output(port.clone());
+    let result = apply(env, meta.clone(), thunk, Vec::new());
+    port::replace_current_output(previous);
+    result?;
+    let string = port.contents().unwrap_or_default();
+    Ok(Item::new(meta, Exp::String(Gc::new(string))))
+}
+
+#[allow(unused_mut)]
+pub fn prim_read_char(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let port = destruct!(env, args, meta; (->..Exp))?;
+    let port = input_port(&meta, 0, port)?;
+    let c = port.read_char().map_err(|e| io_error(&meta, e))?;
+    Ok(Item::new(meta, c.map(Exp::Char).unwrap_or(Exp::Eof)))
+}
+
+#[allow(unused_mut)]
+pub fn prim_peek_char(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let port = destruct!(env, args, meta; (->..Exp))?;
+    let port = input_port(&meta, 0, port)?;
+    let c = port.peek_char().map_err(|e| io_error(&meta, e))?;
+    Ok(Item::new(meta, c.map(Exp::Char).unwrap_or(Exp::Eof)))
+}
+
+#[allow(unused_mut)]
+pub fn prim_read_line(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let port = destruct!(env, args, meta; (->..Exp))?;
+    let port = input_port(&meta, 0, port)?;
+    let line = port.read_line().map_err(|e| io_error(&meta, e))?;
+    Ok(Item::new(meta, string_or_eof(line)))
+}
+
+/// `(read-string k [port])` reads up to `k` characters.
+#[allow(unused_mut)]
+pub fn prim_read_string(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (k, port) = destruct!(env, args, meta; (->Exp::Number) (->..Exp))?;
+    let port = input_port(&meta, 1, port)?;
+    if k < 0.0 || k.fract() != 0.0 {
+        return Err(Exn::typ(meta, "non-negative integer", &k.to_string()));
+    }
+    let string = port
+        .read_string(k as usize)
+        .map_err(|e| io_error(&meta, e))?;
+    Ok(Item::new(meta, string_or_eof(string)))
+}
+
+#[allow(unused_mut)]
+pub fn prim_write_char(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (c, port) = destruct!(env, args, meta; (->Exp::Char) (->..Exp))?;
+    let port = output_port(&meta, 1, port)?;
+    port.write_str(&c.to_string())
+        .map_err(|e| io_error(&meta, e))?;
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+#[allow(unused_mut)]
+pub fn prim_write_string(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (string, port) = destruct!(env, args, meta; (->Exp::String) (->..Exp))?;
+    let port = output_port(&meta, 1, port)?;
+    port.write_str(&string).map_err(|e| io_error(&meta, e))?;
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+#[allow(unused_mut)]
+pub fn prim_newline(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let port = destruct!(env, args, meta; (->..Exp))?;
+    let port = output_port(&meta, 0, port)?;
+    port.write_str("\n").map_err(|e| io_error(&meta, e))?;
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+/// `(display obj [port])` writes `obj` without escaping strings and characters.
+#[allow(unused_mut)]
+pub fn prim_display(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (obj, port) = destruct!(env, args, meta; (->Exp) (->..Exp))?;
+    let port = output_port(&meta, 1, port)?;
+    port.write_str(&obj.to_string())
+        .map_err(|e| io_error(&meta, e))?;
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+/// `(write obj [port])` writes `obj` so that `read` can read it back in.
+/// Objects that are part of a cycle are written with datum labels.
+pub fn prim_write(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    write_with(env, meta, args, Sharing::Cycles)
+}
+
+/// `(write-shared obj [port])` is `write` with datum labels for all objects
+/// that occur more than once in `obj`.
+pub fn prim_write_shared(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    write_with(env, meta, args, Sharing::All)
+}
+
+/// `(write-simple obj [port])` is `write` without datum labels, which loops
+/// forever on cyclic structure.
+pub fn prim_write_simple(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    write_with(env, meta, args, Sharing::None)
+}
+
+#[allow(unused_mut)]
+fn write_with(env: &mut Env, meta: Meta, args: Item, sharing: Sharing) -> Result<Item, Exn> {
+    let (obj, port) = destruct!(env, args, meta; (->Exp) (->..Exp))?;
+    let port = output_port(&meta, 1, port)?;
+    port.write_str(&write::to_string(&obj.exp, true, sharing))
+        .map_err(|e| io_error(&meta, e))?;
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+/// `(pretty-print obj [port] [width])` writes `obj` like `write`, broken into
+/// indented lines to fit `width` columns, followed by a newline. A single
+/// number after `obj` is the width.
+#[allow(unused_mut)]
+pub fn prim_pretty_print(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (obj, mut rest) = destruct!(env, args, meta; (->Exp) (->..Exp))?;
+    if rest.len() > 2 {
+        let arities = vec![Arity::Exact(1), Arity::Exact(2), Arity::Exact(3)];
+        return Err(Exn::new(
+            meta,
+            Condition::Arity(Arity::OneOf(arities), 1 + rest.len()),
+        ));
+    }
+    let width = match rest.last() {
+        Some(Exp::Number(_)) if rest.len() == 1 => rest.pop(),
+        Some(_) if rest.len() == 2 => rest.pop(),
+        _ => None,
+    };
+    let width = match width {
+        Some(Exp::Number(n)) if n > 0.0 && n.fract() == 0.0 => n as usize,
+        Some(x) => return Err(Exn::typ(meta, "positive integer", &x.type_name())),
+        None => DEFAULT_WIDTH,
+    };
+    let port = output_port(&meta, 1, rest)?;
+    let text = pretty(&obj.exp, width, 0);
+    port.write_str(&format!("{}\n", text))
+        .map_err(|e| io_error(&meta, e))?;
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+#[allow(unused_mut)]
+pub fn prim_close_port(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let port = destruct!(env, args, meta; (->Exp::Port))?;
+    port.close().map_err(|e| io_error(&meta, e))?;
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+#[allow(unused_mut)]
+pub fn prim_eof_object(_: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    destruct!(env, args, meta;)?;
+    Ok(Item::new(meta, Exp::Eof))
+}
+
+#[allow(unused_mut)]
+pub fn prim_is_eof_object(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let x = destruct!(env, args, meta; (->Exp))?;
+    Ok(Item::new(meta, Exp::Boolean(matches!(x.exp, Exp::Eof))))
+}
@@ -1,7 +1,8 @@
 use crate::ast::*;
+use crate::gc::Gc;
 use crate::runtime::*;
+use crate::symbol::Symbol;
 use crate::Exception::*;
-use std::rc::Rc;
 
 pub fn prim_define(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
     let (left, right) = destruct!(env, args, meta; (Item) (Item))?;
@@ -9,16 +10,23 @@ pub fn prim_define(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
         Exp::Symbol(key) => {
             let val = eval(env, &right)?;
             env.set(key, val);
-            Ok(Item::new(meta, Exp::Nil))
+            Ok(Item::new(meta, Exp::Unspecified))
         }
-        Exp::Pair(_) => {
-            let (key, params) = destruct!(env, left, meta; (Exp::Symbol) (..Exp::Symbol))?;
-            let lambda = Exp::Lambda(Lambda {
-                params: Rc::new(params),
-                body: Rc::new(right),
-            });
+        Exp::Pair(ref signature) => {
+            let name = signature.car();
+            let key = match name.exp {
+                Exp::Symbol(key) => key,
+                x => return Err(Exn::typ(name.meta, "symbol", &x.type_name())),
+            };
+            let lambda = Exp::Lambda(Gc::new(Lambda {
+                clauses: vec![Clause {
+                    params: Params::parse(&signature.cdr())?,
+                    body: right,
+                }],
+                env: env.clone(),
+            }));
             env.set(key, Item::new(left.meta, lambda));
-            Ok(Item::new(meta, Exp::Nil))
+            Ok(Item::new(meta, Exp::Unspecified))
         }
         _ => Err(Exn::typ(
             left.meta,
@@ -28,14 +36,59 @@ pub fn prim_define(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
     }
 }
 
+/// `(lambda formals body)`, where `formals` is a list of parameters that may
+/// end in `. rest`, or a single symbol bound to the list of all arguments.
 #[allow(unused_mut)]
-pub fn prim_lambda(_: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
-    let (params, body) = destruct!(env, args, meta; (Item) (Item))?;
-    let param_names = destruct!(env, params, meta; (..Exp::Symbol))?;
-    Ok(Item::new(meta, Exp::Lambda(Lambda {
-        params: Rc::new(param_names),
-        body: Rc::new(body),
-    })))
+pub fn prim_lambda(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (formals, body) = destruct!(env, args, meta; (Item) (Item))?;
+    Ok(Item::new(meta, Exp::Lambda(Gc::new(Lambda {
+        clauses: vec![Clause {
+            params: Params::parse(&formals)?,
+            body,
+        }],
+        env: env.clone(),
+    }))))
+}
+
+/// `(case-lambda (formals body) ...)` makes a procedure that runs the body of
+/// the first clause whose formals accept the number of arguments it is called with.
+#[allow(unused_mut)]
+pub fn prim_case_lambda(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let clauses = destruct!(env, args, meta; (Item) (..Item))?;
+    let mut parsed = Vec::new();
+    for clause in std::iter::once(clauses.0).chain(clauses.1) {
+        let (formals, body) = destruct!(env, clause, meta; (Item) (Item))?;
+        parsed.push(Clause {
+            params: Params::parse(&formals)?,
+            body,
+        });
+    }
+    Ok(Item::new(meta, Exp::Lambda(Gc::new(Lambda {
+        clauses: parsed,
+        env: env.clone(),
+    }))))
+}
+
+/// `(apply proc arg ... list)` calls `proc` with the `arg`s followed by the
+/// elements of `list`.
+#[allow(unused_mut)]
+pub fn prim_apply(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (proc, mut rest) = destruct!(env, args, meta; (->Exp) (->..Exp))?;
+    let spread = match rest.pop() {
+        Some(spread) => spread,
+        None => return Err(Exn::new(meta, Condition::Arity(Arity::AtLeast(2), 1))),
+    };
+    if !spread.is_list() {
+        return Err(Exn::typ(meta, "list", &spread.type_name()));
+    }
+    let mut call_args: Vec<Item> = rest
+        .into_iter()
+        .map(|arg| Item::new(meta.clone(), arg))
+        .collect();
+    if let Exp::Pair(list) = spread {
+        call_args.extend(list);
+    }
+    apply(env, meta, proc, call_args)
 }
 
 pub fn prim_if(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
@@ -47,14 +100,26 @@ pub fn prim_if(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
     }
 }
 
+/// `(begin expr ...)` evaluates the expressions in order and returns the value
+/// of the last one.
+#[allow(unused_mut)]
+pub fn prim_begin(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let body = destruct!(env, args, meta; (..Item))?;
+    let mut result = Item::new(meta, Exp::Unspecified);
+    for expr in body {
+        result = eval(env, &expr)?;
+    }
+    Ok(result)
+}
+
 #[allow(unused_mut)]
 pub fn prim_cond(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
     let branches = destruct!(env, args, meta; (..Item))?;
     for branch in branches {
         let (car, cdr) = destruct!(env, branch, meta; (Item) (..Item))?;
-        if let Exp::Symbol(ref s) = car.exp {
-            if s == "else" {
-                if cdr.len() == 0 {
+        if let Exp::Symbol(s) = car.exp {
+            if s == Symbol::ELSE {
+                if cdr.is_empty() {
                     return Err(Exn::arity(args.meta, 1, 0));
                 }
                 let mut result = Exp::Nil;
@@ -73,7 +138,7 @@ pub fn prim_cond(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
             return Ok(Item::new(meta, result));
         }
     }
-    Ok(Item::new(meta, Exp::Nil))
+    Ok(Item::new(meta, Exp::Unspecified))
 }
 
 #[allow(unused_mut)]
@@ -102,13 +167,27 @@ pub fn prim_and(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
 #[allow(unused_mut)]
 pub fn prim_car(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
     let pair = destruct!(env, args, meta; (->Exp::Pair))?;
-    Ok(Item::new(meta, eval(env, pair.car.as_ref()).map(|i| i.exp)?))
+    Ok(Item::new(meta, pair.car().exp))
 }
 
 #[allow(unused_mut)]
 pub fn prim_cdr(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
     let pair = destruct!(env, args, meta; (->Exp::Pair))?;
-    Ok(Item::new(meta, eval(env, pair.cdr.as_ref()).map(|i| i.exp)?))
+    Ok(Item::new(meta, pair.cdr().exp))
+}
+
+#[allow(unused_mut)]
+pub fn prim_set_car(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (pair, car) = destruct!(env, args, meta; (->Exp::Pair) (->Exp))?;
+    pair.set_car(car);
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+#[allow(unused_mut)]
+pub fn prim_set_cdr(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (pair, cdr) = destruct!(env, args, meta; (->Exp::Pair) (->Exp))?;
+    pair.set_cdr(cdr);
+    Ok(Item::new(meta, Exp::Unspecified))
 }
 
 pub fn prim_cons(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
@@ -118,7 +197,7 @@ pub fn prim_cons(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
 
 pub fn prim_list(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
     if let Exp::Pair(list) = args.exp {
-        Ok(Item::new(meta, Exp::Pair(eval_list(env, list)?)))
+        Ok(Item::list(meta, eval_list(env, list)?))
     } else {
         Ok(Item::new(meta, Exp::Nil))
     }
@@ -130,9 +209,3 @@ pub fn prim_quote(_: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
     Ok(Item::new(meta, datum))
 }
 
-#[allow(unused_mut)]
-pub fn prim_display(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
-    let arg = destruct!(env, args, meta; (->Exp))?;
-    println!("{}", arg);
-    Ok(Item::new(meta, Exp::Nil))
-}
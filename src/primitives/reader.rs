@@ -0,0 +1,141 @@
+//! Reading data in s-expression syntax with the parser (R7RS 6.13.2).
+use crate::ast::*;
+use crate::gc::Gc;
+use crate::parser::*;
+use crate::port::{self, Port};
+use crate::runtime::*;
+use crate::Exception::*;
+
+/// The port to read from: the given input port, a port reading the given
+/// string, or the current input port.
+fn source(meta: &Meta, args: Vec<Exp>) -> Result<Gc<Port>, Exn> {
+    match args.as_slice() {
+        [] => Ok(port::current_input()),
+        [Exp::Port(port)] if port.is_input => Ok(port.clone()),
+        [Exp::String(string)] => Ok(Gc::new(Port::input_string(string))),
+        [x] => Err(Exn::typ(
+            meta.clone(),
+            "input port or string",
+            &x.type_name(),
+        )),
+        _ => Err(Exn::new(
+            meta.clone(),
+            Condition::Arity(
+                Arity::OneOf(vec![Arity::Exact(0), Arity::Exact(1)]),
+                args.len(),
+            ),
+        )),
+    }
+}
+
+/// Reads data from a port. The port is scanned once for all the data read,
+/// a line at a time, and what follows the last datum on its line is put back
+/// into it by `finish`.
+struct Reader {
+    port: Gc<Port>,
+    scanner: Scanner,
+}
+
+impl Reader {
+    fn new(port: Gc<Port>) -> Reader {
+        let lines = port.clone();
+        Reader {
+            port,
+            scanner: Scanner::lines(Box::new(move || lines.read_line())),
+        }
+    }
+
+    /// Reads the next datum, `None` at the end of input.
+    fn read(&mut self, meta: &Meta) -> Result<Option<Item>, Exn> {
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+        // the data still needed at the top level: the datum itself, and one
+        // more for each datum comment there
+        let mut needed = 1;
+        while needed > 0 && depth >= 0 {
+            let token = match self.scanner.next() {
+                Some(Piece::Token(token)) | Some(Piece::DatumComment(token)) => token,
+                Some(Piece::Trivia(_)) => continue,
+                None => break,
+            };
+            match token.text() {
+                "(" | "#(" => depth += 1,
+                ")" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                match token.text() {
+                    "#;" => needed += 1,
+                    // a quote or a label stands before the datum
+                    text if text == "'" || label(text, '=').is_some() => {}
+                    _ => needed -= 1,
+                }
+            }
+            tokens.push(token);
+        }
+        if let Some(e) = self.scanner.error.take() {
+            return Err(Exn::io(meta.clone(), &e.to_string()));
+        }
+        // an unterminated block comment is left for the parser to report
+        tokens.extend(self.scanner.unterminated.take());
+        let tokens = skip_datum_comments(tokens);
+        if tokens.is_empty() {
+            return Ok(None);
+        }
+        let (datum, _) = parse(&tokens)?;
+        Ok(Some(datum))
+    }
+
+    /// Puts the rest of the line back into the port, from the next token on.
+    /// A line with only whitespace or a comment left is consumed.
+    fn finish(self, meta: &Meta) -> Result<(), Exn> {
+        let rest = self.scanner.rest();
+        let rest = rest.trim_start();
+        if rest.is_empty() || rest.starts_with(';') {
+            return Ok(());
+        }
+        self.port
+            .unread(rest)
+            .map_err(|e| Exn::io(meta.clone(), &e.to_string()))
+    }
+}
+
+/// `(read [port-or-string])` returns the next datum without evaluating it, or
+/// the eof object at the end of input.
+#[allow(unused_mut)]
+pub fn prim_read(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let mut reader = Reader::new(source(&meta, destruct!(env, args, meta; (->..Exp))?)?);
+    let datum = reader.read(&meta)?;
+    reader.finish(&meta)?;
+    match datum {
+        Some(datum) => Ok(datum),
+        None => Ok(Item::new(meta, Exp::Eof)),
+    }
+}
+
+/// `(read-all [port-or-string])` returns the list of all remaining data.
+#[allow(unused_mut)]
+pub fn prim_read_all(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let mut reader = Reader::new(source(&meta, destruct!(env, args, meta; (->..Exp))?)?);
+    let mut data = Vec::new();
+    while let Some(datum) = reader.read(&meta)? {
+        data.push(datum);
+    }
+    reader.finish(&meta)?;
+    Ok(Item::list(meta, data))
+}
+
+/// `(string->datum string)` parses a string containing exactly one datum.
+#[allow(unused_mut)]
+pub fn prim_string_to_datum(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let string = destruct!(env, args, meta; (->Exp::String))?;
+    let mut reader = Reader::new(Gc::new(Port::input_string(&string)));
+    let datum = match reader.read(&meta)? {
+        Some(datum) => datum,
+        None => return Err(Exn::syntax(meta, "Expected a datum, found nothing")),
+    };
+    if let Some(extra) = reader.read(&meta)? {
+        return Err(Exn::syntax(extra.meta, "Expected a single datum"));
+    }
+    Ok(datum)
+}
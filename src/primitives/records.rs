@@ -0,0 +1,162 @@
+//! Record types (R7RS 5.5).
+use crate::ast::*;
+use crate::gc::Gc;
+use crate::record::{Record, RecordType};
+use crate::runtime::*;
+use crate::symbol::Symbol;
+use crate::Exception::*;
+
+fn symbol(item: &Item) -> Result<Symbol, Exn> {
+    match item.exp {
+        Exp::Symbol(s) => Ok(s),
+        ref x => Err(Exn::typ(item.meta.clone(), "symbol", &x.type_name())),
+    }
+}
+
+/// Makes a procedure taking `params` that calls the primitive `prim` with `args`.
+fn procedure(env: &Env, meta: &Meta, params: Vec<Symbol>, prim: Primitive, args: Vec<Exp>) -> Item {
+    let mut body = vec![Item::prim(meta.clone(), prim)];
+    body.extend(args.into_iter().map(|exp| Item::new(meta.clone(), exp)));
+    Item::new(
+        meta.clone(),
+        Exp::Lambda(Gc::new(Lambda {
+            clauses: vec![Clause {
+                params: Params {
+                    required: params,
+                    rest: None,
+                },
+                body: Item::list(meta.clone(), body),
+            }],
+            env: env.clone(),
+        })),
+    )
+}
+
+/// `(define-record-type <name> (constructor field ...) predicate (field accessor [modifier]) ...)`
+///
+/// Fields that are not initialized by the constructor start out as `()`.
+#[allow(unused_mut)]
+pub fn prim_define_record_type(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (name, constructor, predicate, specs) =
+        destruct!(env, args, meta; (Item) (Item) (Item) (..Item))?;
+    let name = symbol(&name)?;
+    let predicate = symbol(&predicate)?;
+
+    let mut fields = Vec::new();
+    let mut procedures = Vec::new(); // (name, index, is_modifier)
+    for spec in specs {
+        let (field, accessor, modifier) = destruct!(env, spec, meta; (Item) (Item) (..Item))?;
+        let field = symbol(&field)?;
+        if fields.contains(&field) {
+            return Err(Exn::other(meta, &format!("duplicate field: {}", field)));
+        }
+        procedures.push((symbol(&accessor)?, fields.len(), false));
+        match modifier.as_slice() {
+            [] => {}
+            [modifier] => procedures.push((symbol(modifier)?, fields.len(), true)),
+            _ => return Err(Exn::arity(meta, 3, modifier.len() + 2)),
+        }
+        fields.push(field);
+    }
+    let rtd = Gc::new(RecordType::new(name, fields));
+
+    let (constructor, params) = match constructor.exp {
+        Exp::Symbol(s) => (s, rtd.fields.clone()),
+        Exp::Pair(ref cell) => {
+            let mut params = Vec::new();
+            for item in cell.clone().into_iter().skip(1) {
+                let param = symbol(&item)?;
+                if rtd.index(param).is_none() {
+                    return Err(Exn::other(item.meta, &format!("unknown field: {}", param)));
+                }
+                params.push(param);
+            }
+            (symbol(&cell.car())?, params)
+        }
+        ref x => {
+            return Err(Exn::typ(
+                constructor.meta.clone(),
+                "symbol or pair",
+                &x.type_name(),
+            ))
+        }
+    };
+    let inits = rtd.fields.iter().map(|f| match params.contains(f) {
+        true => Exp::Symbol(*f),
+        false => Exp::Nil,
+    });
+    let rtd_exp = Exp::RecordType(rtd.clone());
+    let args = std::iter::once(rtd_exp.clone()).chain(inits).collect();
+    env.set(
+        constructor,
+        procedure(env, &meta, params, prim_record_construct, args),
+    );
+
+    let record = Symbol::intern("record");
+    let value = Symbol::intern("value");
+    let args = vec![rtd_exp.clone(), Exp::Symbol(record)];
+    env.set(
+        predicate,
+        procedure(env, &meta, vec![record], prim_record_predicate, args),
+    );
+    for (name, i, is_modifier) in procedures {
+        let index = Exp::Number(i as f64);
+        let proc = if is_modifier {
+            let args = vec![
+                rtd_exp.clone(),
+                Exp::Symbol(record),
+                index,
+                Exp::Symbol(value),
+            ];
+            procedure(env, &meta, vec![record, value], prim_record_set, args)
+        } else {
+            let args = vec![rtd_exp.clone(), Exp::Symbol(record), index];
+            procedure(env, &meta, vec![record], prim_record_ref, args)
+        };
+        env.set(name, proc);
+    }
+    env.set(name, Item::new(meta.clone(), rtd_exp));
+    Ok(Item::new(meta, Exp::Unspecified))
+}
+
+/// Returns the record in `item` if it is an instance of `rtd`.
+fn instance(rtd: &Gc<RecordType>, item: Item) -> Result<Gc<Record>, Exn> {
+    match item.exp {
+        Exp::Record(record) if Gc::ptr_eq(&record.rtd, rtd) => Ok(record),
+        x => Err(Exn::typ(item.meta, &rtd.name, &x.type_name())),
+    }
+}
+
+#[allow(unused_mut)]
+fn prim_record_construct(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (rtd, fields) = destruct!(env, args, meta; (->Exp::RecordType) (->..Exp))?;
+    let fields = fields
+        .into_iter()
+        .map(|exp| Item::new(meta.clone(), exp))
+        .collect();
+    Ok(Item::new(
+        meta,
+        Exp::Record(Gc::new(Record::new(rtd, fields))),
+    ))
+}
+
+#[allow(unused_mut)]
+fn prim_record_predicate(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (rtd, x) = destruct!(env, args, meta; (->Exp::RecordType) (->Exp))?;
+    let is_instance = matches!(x.exp, Exp::Record(record) if Gc::ptr_eq(&record.rtd, &rtd));
+    Ok(Item::new(meta, Exp::Boolean(is_instance)))
+}
+
+#[allow(unused_mut)]
+fn prim_record_ref(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (rtd, record, i) = destruct!(env, args, meta; (->Exp::RecordType) (->Exp) (Exp::Number))?;
+    Ok(instance(&rtd, record)?.get(i as usize))
+}
+
+#[allow(unused_mut)]
+fn prim_record_set(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (rtd, record, i, val) =
+        destruct!(env, args, meta; (->Exp::RecordType) (->Exp) (Exp::Number) (->Exp))?;
+    instance(&rtd, record)?.set(i as usize, val);
+    Ok(Item::new(meta, Exp::Unspecified))
+}
@@ -0,0 +1,11 @@
+//! String procedures (R7RS 6.7).
+use crate::ast::*;
+use crate::runtime::*;
+use crate::Exception::*;
+
+#[allow(unused_mut)]
+pub fn prim_string_eq(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (first, rest) = destruct!(env, args, meta; (->Exp::String) (->..Exp::String))?;
+    let eq = rest.iter().all(|s| **s == *first);
+    Ok(Item::new(meta, Exp::Boolean(eq)))
+}
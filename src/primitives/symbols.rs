@@ -0,0 +1,43 @@
+use crate::ast::*;
+use crate::gc::Gc;
+use crate::runtime::*;
+use crate::symbol::Symbol;
+use crate::Exception::*;
+
+#[allow(unused_mut)]
+pub fn prim_symbol_to_string(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let sym = destruct!(env, args, meta; (->Exp::Symbol))?;
+    Ok(Item::new(
+        meta,
+        Exp::String(Gc::new(sym.name().to_string())),
+    ))
+}
+
+#[allow(unused_mut)]
+pub fn prim_string_to_symbol(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let name = destruct!(env, args, meta; (->Exp::String))?;
+    Ok(Item::new(meta, Exp::Symbol(Symbol::intern(&name))))
+}
+
+/// `(gensym [prefix])` returns a fresh, uninterned symbol. The prefix may be a
+/// symbol or a string and defaults to `g`.
+#[allow(unused_mut)]
+pub fn prim_gensym(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    if let Exp::Nil = args.exp {
+        return Ok(Item::new(meta, Exp::Symbol(Symbol::gensym("g"))));
+    }
+    let prefix = destruct!(env, args, meta; (->Exp))?;
+    let sym = match prefix.exp {
+        Exp::Symbol(s) => Symbol::gensym(&s.name()),
+        Exp::String(s) => Symbol::gensym(&s),
+        x => return Err(Exn::typ(prefix.meta, "symbol or string", &x.type_name())),
+    };
+    Ok(Item::new(meta, Exp::Symbol(sym)))
+}
+
+#[allow(unused_mut)]
+pub fn prim_symbol_eq(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (first, rest) = destruct!(env, args, meta; (->Exp::Symbol) (->..Exp::Symbol))?;
+    let eq = rest.iter().all(|s| *s == first);
+    Ok(Item::new(meta, Exp::Boolean(eq)))
+}
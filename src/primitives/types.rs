@@ -0,0 +1,197 @@
+//! Type predicates, conversions between types and `type-of`.
+use crate::ast::*;
+use crate::gc::Gc;
+use crate::runtime::*;
+use crate::symbol::Symbol;
+use crate::Exception::*;
+
+#[allow(unused_mut)]
+fn predicate(env: &mut Env, meta: Meta, args: Item, test: fn(&Exp) -> bool) -> Result<Item, Exn> {
+    let x = destruct!(env, args, meta; (->Exp))?;
+    Ok(Item::new(meta, Exp::Boolean(test(&x.exp))))
+}
+
+pub fn prim_is_number(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    predicate(env, meta, args, |x| matches!(x, Exp::Number(_)))
+}
+
+pub fn prim_is_pair(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    predicate(env, meta, args, |x| matches!(x, Exp::Pair(_)))
+}
+
+pub fn prim_is_null(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    predicate(env, meta, args, |x| matches!(x, Exp::Nil))
+}
+
+pub fn prim_is_list(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    predicate(env, meta, args, Exp::is_list)
+}
+
+pub fn prim_is_symbol(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    predicate(env, meta, args, |x| matches!(x, Exp::Symbol(_)))
+}
+
+pub fn prim_is_string(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    predicate(env, meta, args, |x| matches!(x, Exp::String(_)))
+}
+
+pub fn prim_is_boolean(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    predicate(env, meta, args, |x| matches!(x, Exp::Boolean(_)))
+}
+
+pub fn prim_is_procedure(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    predicate(env, meta, args, |x| {
+        matches!(x, Exp::Primitive(_) | Exp::Lambda(_))
+    })
+}
+
+pub fn prim_is_vector(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    predicate(env, meta, args, |x| matches!(x, Exp::Vector(_)))
+}
+
+pub fn prim_is_char(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    predicate(env, meta, args, |x| matches!(x, Exp::Char(_)))
+}
+
+/// `(type-of obj)` returns the type of `obj` as a symbol, like `pair` or `procedure`.
+#[allow(unused_mut)]
+pub fn prim_type_of(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let x = destruct!(env, args, meta; (->Exp))?;
+    let name = match x.exp {
+        Exp::Nil => "null".to_string(),
+        Exp::Primitive(_) | Exp::Lambda(_) => "procedure".to_string(),
+        exp => exp.type_name(),
+    };
+    Ok(Item::new(meta, Exp::Symbol(Symbol::intern(&name))))
+}
+
+/// `(string->number string [radix])` returns `#f` if `string` is not a number.
+/// The prefixes `#b`, `#o`, `#d` and `#x` override the radix, which defaults
+/// to 10. Only radix 10 allows decimals and exponents.
+pub fn prim_string_to_number(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (string, radix) = destruct!(env, args, meta; (->Exp::String) (->..Exp::Number))?;
+    let radix = match radix[..] {
+        [] => 10,
+        [radix] => check_radix(&meta, radix)?,
+        _ => return Err(Exn::arity(meta, 2, radix.len() + 1)),
+    };
+    let number = parse_number(&string, radix)
+        .map(Exp::Number)
+        .unwrap_or(Exp::Boolean(false));
+    Ok(Item::new(meta, number))
+}
+
+fn check_radix(meta: &Meta, radix: f64) -> Result<u32, Exn> {
+    match radix as u32 {
+        2 | 8 | 10 | 16 if radix.fract() == 0.0 => Ok(radix as u32),
+        _ => Err(Exn::other(
+            meta.clone(),
+            "radix must be one of 2, 8, 10 or 16",
+        )),
+    }
+}
+
+fn parse_number(string: &str, radix: u32) -> Option<f64> {
+    let (radix, digits) = match string.get(..2) {
+        Some("#b") => (2, &string[2..]),
+        Some("#o") => (8, &string[2..]),
+        Some("#d") => (10, &string[2..]),
+        Some("#x") => (16, &string[2..]),
+        _ => (radix, string),
+    };
+    if radix == 10 {
+        // rust also accepts words like "inf" and "NaN" that are symbols in skim
+        let numeric = digits
+            .chars()
+            .all(|c| c.is_ascii_digit() || "+-.eE".contains(c));
+        if numeric {
+            digits.parse().ok()
+        } else {
+            None
+        }
+    } else {
+        i64::from_str_radix(digits, radix).ok().map(|x| x as f64)
+    }
+}
+
+/// `(number->string z [radix])`, radixes other than 10 only work for integers.
+pub fn prim_number_to_string(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let (z, radix) = destruct!(env, args, meta; (->Exp::Number) (->..Exp::Number))?;
+    let radix = match radix[..] {
+        [] => 10,
+        [radix] => check_radix(&meta, radix)?,
+        _ => return Err(Exn::arity(meta, 2, radix.len() + 1)),
+    };
+    let string = if radix == 10 {
+        z.to_string()
+    } else if z.fract() == 0.0 && z.abs() < i64::MAX as f64 {
+        format_radix(z as i64, radix)
+    } else {
+        return Err(Exn::other(
+            meta,
+            "only integers can be converted with a radix other than 10",
+        ));
+    };
+    Ok(Item::new(meta, Exp::String(Gc::new(string))))
+}
+
+fn format_radix(z: i64, radix: u32) -> String {
+    let mut digits = Vec::new();
+    let mut rest = z.unsigned_abs();
+    loop {
+        digits.push(std::char::from_digit((rest % radix as u64) as u32, radix).unwrap());
+        rest /= radix as u64;
+        if rest == 0 {
+            break;
+        }
+    }
+    if z < 0 {
+        digits.push('-');
+    }
+    digits.iter().rev().collect()
+}
+
+/// `(list->string list)` builds a string from a list of characters.
+#[allow(unused_mut)]
+pub fn prim_list_to_string(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let list = destruct!(env, args, meta; (->Exp))?;
+    // a circular list would never end
+    if !list.exp.is_list() {
+        return Err(Exn::typ(list.meta, "list", &list.exp.type_name()));
+    }
+    let mut string = String::new();
+    let mut rest = list.exp;
+    loop {
+        match rest {
+            Exp::Nil => break,
+            Exp::Pair(cell) => {
+                let item = cell.car();
+                match item.exp {
+                    Exp::Char(c) => string.push(c),
+                    x => return Err(Exn::typ(item.meta, "char", &x.type_name())),
+                }
+                rest = cell.cdr().exp;
+            }
+            x => return Err(Exn::typ(list.meta, "list", &x.type_name())),
+        }
+    }
+    Ok(Item::new(meta, Exp::String(Gc::new(string))))
+}
+
+/// `(string->list string)` returns the characters of `string`.
+#[allow(unused_mut)]
+pub fn prim_string_to_list(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let string = destruct!(env, args, meta; (->Exp::String))?;
+    let chars = string
+        .chars()
+        .map(|c| Item::new(meta.clone(), Exp::Char(c)))
+        .collect();
+    Ok(Item::list(meta, chars))
+}
+
+/// `(exact->inexact z)`, all skim numbers are inexact so this returns `z`.
+#[allow(unused_mut)]
+pub fn prim_exact_to_inexact(env: &mut Env, meta: Meta, args: Item) -> Result<Item, Exn> {
+    let z = destruct!(env, args, meta; (->Exp::Number))?;
+    Ok(Item::new(meta, Exp::Number(z)))
+}
@@ -4,7 +4,8 @@
 /// `destruct!(env, args, "prim_name"; (Exp) (Exp::Number) (->Exp) ...)`
 ///
 /// `env`, `args` and `"prim_name"` are required arguments, always followed by a semicolon and a list
-/// of 0 or more argument matchers. The returned Tuple has a field with the contained value for each specified matcher.
+/// of 0 or more argument matchers. The returned Tuple has a field with the contained value for each specified matcher,
+/// a single matcher returns its value directly and no matchers return `()`.
 ///
 /// ### Matchers and their return types
 ///
@@ -12,7 +13,7 @@
 ///
 /// `(Exp::Number)` -> `f64`
 ///
-/// `(Exp::Pair)` -> `LispCell`
+/// `(Exp::Pair)` -> `Gc<LispCell>`
 ///
 /// This works for any variant of the `Exp` Enum.
 ///
@@ -22,133 +23,132 @@
 /// Prefix with `->` like `(->Exp::Pair)` or `(->..Exp)` to evaluate arguments before matching.
 macro_rules! destruct {
     (@void $tt:tt) => {};
-    (@type_name Exp::Number) => { "number" };
-    (@type_name Exp::Boolean) => { "boolean" };
-    (@type_name Exp::Pair) => { "pair" };
-    (@arg($env:ident, $list:ident) (Item)) => { // (->Exp)
-        *$list.car
-    };
-    (@arg($env:ident, $list:ident) (..Item)) => { // (->Exp)
+    (@fixed (..$($tt:tt)*)) => { 0usize };
+    (@fixed (->..$($tt:tt)*)) => { 0usize };
+    (@fixed $tt:tt) => { 1usize };
+    (@match $item:expr, $pat:path) => {
         {
-            let mut vec: Vec<Item> = Vec::new();
-            vec.push(*$list.car);
-            while let Exp::Pair(cdr) = $list.cdr.exp {
-                $list = cdr;
-                vec.push(*$list.car);
+            let item = $item;
+            if let $pat(x) = item.exp {
+                x
+            } else {
+                return Err(Exn::typ(
+                    item.meta,
+                    &crate::primitives::util::pattern_type_name(stringify!($pat)),
+                    &item.exp.type_name(),
+                ));
             }
-            vec
         }
     };
-    (@arg($env:ident, $list:ident) (->Exp)) => { // (->Exp)
-        eval($env, &*$list.car)?
-    };
-    (@arg($env:ident, $list:ident) (Exp)) => { // (Exp)
-        $list.car.exp
+    (@next $rest:ident, $arity:ident) => { // takes the next argument off the list
+        if let Exp::Pair(cell) = $rest {
+            $rest = cell.cdr().exp;
+            cell.car()
+        } else {
+            return Err($arity()); // got too few arguments
+        }
     };
-    (@arg($env:ident, $list:ident) (->..Exp)) => { // (->..Exp)
+    (@rest $rest:ident) => { // takes all remaining arguments off the list
         {
-            let mut vec: Vec<Exp> = Vec::new();
-            vec.push(eval($env, &*$list.car)?.exp);
-            while let Exp::Pair(cdr) = $list.cdr.exp {
-                $list = cdr;
-                vec.push(eval($env, &*$list.car)?.exp);
+            let mut vec: Vec<Item> = Vec::new();
+            while let Exp::Pair(cell) = $rest {
+                $rest = cell.cdr().exp;
+                vec.push(cell.car());
             }
             vec
         }
     };
-    (@arg($env:ident, $list:ident) (..Exp)) => { // (..Exp)
+    (@arg($env:ident, $rest:ident, $arity:ident) (Item)) => { // (Item)
+        destruct!(@next $rest, $arity)
+    };
+    (@arg($env:ident, $rest:ident, $arity:ident) (..Item)) => { // (..Item)
+        destruct!(@rest $rest)
+    };
+    (@arg($env:ident, $rest:ident, $arity:ident) (->Exp)) => { // (->Exp)
+        eval($env, &destruct!(@next $rest, $arity))?
+    };
+    (@arg($env:ident, $rest:ident, $arity:ident) (Exp)) => { // (Exp)
+        destruct!(@next $rest, $arity).exp
+    };
+    (@arg($env:ident, $rest:ident, $arity:ident) (->..Exp)) => { // (->..Exp)
         {
             let mut vec: Vec<Exp> = Vec::new();
-            vec.push($list.car.exp);
-            while let Exp::Pair(cdr) = $list.cdr.exp {
-                $list = cdr;
-                vec.push($list.car.exp);
+            for item in destruct!(@rest $rest) {
+                vec.push(eval($env, &item)?.exp);
             }
             vec
         }
     };
-    (@arg($env:ident, $list:ident) (->..$pat:path)) => { // (->..Exp::Pair)
+    (@arg($env:ident, $rest:ident, $arity:ident) (..Exp)) => { // (..Exp)
+        destruct!(@rest $rest).into_iter().map(|item| item.exp).collect::<Vec<Exp>>()
+    };
+    (@arg($env:ident, $rest:ident, $arity:ident) (->..$pat:path)) => { // (->..Exp::Pair)
         {
             let mut vec = Vec::new();
-            if let $pat(x) = eval($env, &*$list.car)?.exp {
-                vec.push(x);
-            } else {
-                todo!()
-            }
-            while let Exp::Pair(cdr) = $list.cdr.exp {
-                $list = cdr;
-                if let $pat(x) = eval($env, &*$list.car)?.exp {
-                    vec.push(x);
-                } else {
-                    todo!()
-                }
+            for item in destruct!(@rest $rest) {
+                vec.push(destruct!(@match eval($env, &item)?, $pat));
             }
             vec
         }
     };
-    (@arg($env:ident, $list:ident) (..$pat:path)) => { // (..Exp::Pair)
+    (@arg($env:ident, $rest:ident, $arity:ident) (..$pat:path)) => { // (..Exp::Pair)
         {
             let mut vec = Vec::new();
-            if let $pat(x) = $list.car.exp {
-                vec.push(x);
-            } else {
-                todo!()
-            }
-            while let Exp::Pair(cdr) = $list.cdr.exp {
-                $list = cdr;
-                if let $pat(x) = $list.car.exp {
-                    vec.push(x);
-                } else {
-                    todo!()
-                }
+            for item in destruct!(@rest $rest) {
+                vec.push(destruct!(@match item, $pat));
             }
             vec
         }
     };
-    (@arg($env:ident, $list:ident) (->$pat:path)) => { // (->Exp::Pair)
-        if let $pat(x) = eval($env, &*$list.car)?.exp {
-            x
-        } else {
-            todo!()
-        }
+    (@arg($env:ident, $rest:ident, $arity:ident) (->$pat:path)) => { // (->Exp::Pair)
+        destruct!(@match eval($env, &destruct!(@next $rest, $arity))?, $pat)
     };
-    (@arg($env:ident, $list:ident) ($pat:path)) => { // (Exp::Pair)
-        if let $pat(x) = $list.car.exp {
-            x
-        } else {
-            todo!()
-        }
+    (@arg($env:ident, $rest:ident, $arity:ident) ($pat:path)) => { // (Exp::Pair)
+        destruct!(@match destruct!(@next $rest, $arity), $pat)
     };
-    ($env:ident, $ex:expr, $meta:expr; $first:tt $($arg:tt)*) => {
-        if let Exp::Pair(mut list) = $ex.exp {
-            let (mut expected_args, mut received_args) = (1, 1);
-            $(destruct!(@void $arg); expected_args += 1usize;)* // count expected number of arguments
-            let result = (
-                destruct!(@arg($env, list) $first)
-                $(
-                    ,{
-                        list = if let Exp::Pair(cdr) = list.cdr.exp {
-                            received_args += 1usize;
-                            Ok(cdr)
-                        } else {
-                            Err(Exn::arity($meta.clone(), expected_args, received_args)) // got too few arguments
-                        }?;
-                        destruct!(@arg($env, list) $arg)
+    (@tuple($env:ident, $rest:ident, $arity:ident)) => {
+        ()
+    };
+    (@tuple($env:ident, $rest:ident, $arity:ident) $arg:tt) => {
+        destruct!(@arg($env, $rest, $arity) $arg)
+    };
+    (@tuple($env:ident, $rest:ident, $arity:ident) $($arg:tt)+) => {
+        ($(destruct!(@arg($env, $rest, $arity) $arg)),+)
+    };
+    ($env:ident, $ex:expr, $meta:expr; $($arg:tt)*) => {
+        {
+            let args = $ex.exp;
+            (|| -> Result<_, Exn> {
+                let mut list = args.clone();
+                let expected = 0usize $(+ destruct!(@fixed $arg))*;
+                let matchers = 0usize $(+ { destruct!(@void $arg); 1usize })*;
+                let variadic = expected != matchers;
+                let arity = || {
+                    let mut received = 0;
+                    let mut rest = args.clone();
+                    while let Exp::Pair(cell) = rest {
+                        received += 1;
+                        rest = cell.cdr().exp;
                     }
-                )*
-            );
-            if let Exp::Pair(cdr) = list.cdr.exp {
-                list = cdr;
-                while let Exp::Pair(cdr) = list.cdr.exp {
-                    list = cdr;
-                    received_args += 1;
+                    let expected = if variadic {
+                        Arity::AtLeast(expected)
+                    } else {
+                        Arity::Exact(expected)
+                    };
+                    Exn::new($meta.clone(), Condition::Arity(expected, received))
+                };
+                let result = destruct!(@tuple($env, list, arity) $($arg)*);
+                if let Exp::Pair(_) = list {
+                    return Err(arity()); // got too many arguments
                 }
-                Err(Exn::arity($meta.clone(), expected_args, received_args)) // got too many arguments
-            } else {
                 Ok(result)
-            }
-        } else {
-            Err(Exn::other($ex.meta.clone(), "primitive did note receive a list as input"))
+            })()
         }
     };
 }
+
+/// Turns the stringified path of an `Exp` variant, like `Exp::Number`, into the
+/// type name used in error messages.
+pub(crate) fn pattern_type_name(pattern: &str) -> String {
+    pattern.rsplit("::").next().unwrap_or(pattern).trim().to_lowercase()
+}
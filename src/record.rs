@@ -0,0 +1,80 @@
+use crate::ast::*;
+use crate::gc::{Gc, Trace};
+use crate::symbol::Symbol;
+use std::cell::RefCell;
+
+/// The type descriptor of records made by `define-record-type`.
+pub struct RecordType {
+    /// The type name without the angle brackets, `point` for `<point>`.
+    pub name: String,
+    pub fields: Vec<Symbol>,
+}
+
+/// An instance of a record type.
+pub struct Record {
+    pub rtd: Gc<RecordType>,
+    fields: RefCell<Vec<Item>>,
+}
+
+impl RecordType {
+    pub fn new(name: Symbol, fields: Vec<Symbol>) -> RecordType {
+        let name = name.name();
+        let name = match name.strip_prefix('<').and_then(|n| n.strip_suffix('>')) {
+            Some(inner) if !inner.is_empty() => inner.to_string(),
+            _ => name.to_string(),
+        };
+        RecordType { name, fields }
+    }
+
+    /// The position of `field` in the records of this type.
+    pub fn index(&self, field: Symbol) -> Option<usize> {
+        self.fields.iter().position(|f| *f == field)
+    }
+}
+
+impl Record {
+    pub fn new(rtd: Gc<RecordType>, fields: Vec<Item>) -> Record {
+        Record {
+            rtd,
+            fields: RefCell::new(fields),
+        }
+    }
+
+    pub fn get(&self, i: usize) -> Item {
+        self.fields.borrow()[i].clone()
+    }
+
+    pub fn set(&self, i: usize, val: Item) {
+        self.fields.borrow_mut()[i] = val;
+    }
+}
+
+impl Trace for RecordType {
+    fn trace(&self, _: &mut dyn FnMut(usize)) -> bool {
+        true
+    }
+}
+
+impl Trace for Record {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        if let Ok(fields) = self.fields.try_borrow() {
+            visit(self.rtd.addr());
+            for val in fields.iter() {
+                val.exp.trace(visit);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut fields) = self.fields.try_borrow_mut() {
+            fields.clear();
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        self.fields.borrow().capacity() * std::mem::size_of::<Item>()
+    }
+}
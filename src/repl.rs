@@ -1,36 +1,177 @@
 use crate::ast::*;
+use crate::commands;
+use crate::config::{self, Settings, Theme};
+use crate::helper::ReplHelper;
 use crate::parser::*;
+use crate::pretty::pretty;
+use crate::run::exec_file;
 use crate::runtime::*;
+use crate::sandbox::{self, sandbox_env};
+use crate::symbol::Symbol;
 use crate::Exception::*;
 use rustyline::error::ReadlineError;
-use rustyline::{Config, EditMode, Editor};
+use rustyline::{Config, Editor};
+use std::fs;
+use std::path::Path;
 
-fn parse_eval(code: String, env: &mut Env) -> Result<Item, Exn> {
-    let (parsed_exp, _) = parse(&lex(&code, None))?;
-    Ok(eval(env, &parsed_exp)?)
+/// The width results are printed to fit when the output is not a terminal.
+const DEFAULT_WIDTH: usize = 80;
+
+/// How the REPL is started.
+#[derive(Default)]
+pub struct Options {
+    /// Only offer the primitives of a sandbox environment, and evaluate each
+    /// input under the limits anew.
+    pub sandbox: bool,
+    /// Skip evaluating the init file.
+    pub no_init: bool,
+}
+
+/// The state of a REPL session, which meta-commands work on too.
+pub(crate) struct Session {
+    pub env: Env,
+    pub sandbox: bool,
+    pub theme: Theme,
+    /// The width of the terminal, which results are printed to fit.
+    pub width: usize,
+    /// The number of results so far, which are bound to `$1`, `$2`, ...
+    pub results: usize,
+    /// Set once the session should end.
+    pub quit: bool,
+}
+
+impl Session {
+    /// Binds `value` to the next result variable and prints it, unless it is
+    /// unspecified, like the value of `define`.
+    pub fn print_result(&mut self, value: Item) {
+        if let Exp::Unspecified = value.exp {
+            return;
+        }
+        self.results += 1;
+        let name = format!("${}", self.results);
+        let prefix = format!("{} = ", name);
+        let shown = pretty(&value.exp, self.width, prefix.len());
+        self.env.set(Symbol::intern(&name), value);
+        println!("{}{}", prefix, self.theme.paint(self.theme.result, &shown));
+    }
+
+    /// Evaluates all expressions in `tokens` one after the other and prints
+    /// their values, stopping at the first error.
+    pub fn parse_eval(&mut self, tokens: &[Token]) -> Result<(), Exn> {
+        let mut rest = tokens;
+        while !rest.is_empty() {
+            let (exp, next) = parse(rest)?;
+            let value = eval(&mut self.env, &exp)?;
+            self.print_result(value);
+            rest = next;
+        }
+        Ok(())
+    }
+}
+
+/// Evaluates the init file, which may import libraries and define procedures
+/// for the session, and returns the settings it chose.
+fn init(env: &mut Env) -> Settings {
+    if let Some(file) = config::init_file() {
+        if let Err(e) = exec_file(env, &Meta::empty(), &file.to_string_lossy()) {
+            println!("{}", e);
+        }
+    }
+    Settings::read(env).unwrap_or_else(|e| {
+        println!("{}", e);
+        Settings::default()
+    })
+}
+
+/// Writes the history to `file`, creating its directory if needed.
+fn save_history(rl: &Editor<ReplHelper>, file: &Path) -> rustyline::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    rl.save_history(file)
 }
 
-pub fn repl() {
-    let config = Config::builder().edit_mode(EditMode::Vi).build();
-    let mut env = default_env();
-    let mut rl = Editor::<()>::with_config(config);
-    // if rl.load_history("history.txt").is_err() {
-    //     println!("No previous history.");
-    // }
+/// Starts an interactive session. A sandboxed session skips the init file,
+/// which could not import libraries anyway.
+pub fn repl(options: Options) {
+    let mut env = if options.sandbox {
+        // standard input is for the line editor only
+        sandbox::empty_input();
+        sandbox_env()
+    } else {
+        default_env()
+    };
+    let settings = if options.sandbox || options.no_init {
+        Settings::default()
+    } else {
+        init(&mut env)
+    };
+    let config = Config::builder()
+        .edit_mode(settings.edit_mode)
+        .max_history_size(settings.history_size)
+        .history_ignore_dups(true)
+        .build();
+    let mut session = Session {
+        env,
+        sandbox: options.sandbox,
+        theme: settings.theme,
+        width: DEFAULT_WIDTH,
+        results: 0,
+        quit: false,
+    };
+    let mut rl = Editor::<ReplHelper>::with_config(config);
+    rl.set_helper(Some(ReplHelper::new(session.env.clone(), settings.theme)));
+    let history = config::history_file();
+    if let Some(file) = &history {
+        // there is no history before the first session
+        let _ = rl.load_history(file);
+    }
+    // the lines of an expression that is not complete yet
+    let mut input = String::new();
     loop {
-        let readline = rl.readline(">> ");
+        let prompt = if input.is_empty() {
+            &settings.prompt
+        } else {
+            ".. "
+        };
+        let readline = rl.readline(prompt);
         match readline {
             Ok(line) => {
-                rl.add_history_entry(line.as_str());
-                match parse_eval(line, &mut env) {
-                    Ok(res) => println!("{}", res),
-                    Err(e) => println!("{}", e),
+                if !input.is_empty() {
+                    input.push('\n');
                 }
+                // lines piped in rather than typed keep their line ending
+                input.push_str(line.trim_end_matches(&['\n', '\r'][..]));
+                let tokens = lex(&input, None);
+                if is_incomplete(&tokens) {
+                    continue;
+                }
+                rl.add_history_entry(input.as_str());
+                if let Some(file) = &history {
+                    if let Err(e) = save_history(&rl, file) {
+                        println!("could not save history to {}: {}", file.display(), e);
+                    }
+                }
+                sandbox::restart();
+                session.width = rl.dimensions().map_or(DEFAULT_WIDTH, |(columns, _)| columns);
+                let result = if commands::is_command(&input) {
+                    commands::run(&mut session, &input)
+                } else {
+                    session.parse_eval(&tokens)
+                };
+                if let Err(e) = result {
+                    let theme = &settings.theme;
+                    println!("{}", theme.paint(theme.error, &e.to_string()));
+                }
+                input.clear();
+                if session.quit {
+                    break;
+                }
+                // ,reset replaces the environment
+                rl.set_helper(Some(ReplHelper::new(session.env.clone(), settings.theme)));
             }
-            Err(ReadlineError::Interrupted) => {
-                println!("CTRL-C");
-                break;
-            }
+            // discards the expression being typed, which may span lines
+            Err(ReadlineError::Interrupted) => input.clear(),
             Err(ReadlineError::Eof) => {
                 println!("CTRL-D");
                 break;
@@ -41,5 +182,4 @@ pub fn repl() {
             }
         }
     }
-    // rl.save_history("history.txt").unwrap();
 }
@@ -1,24 +1,71 @@
+use crate::ast::*;
+use crate::library::add_search_path;
 use crate::parser::*;
 use crate::runtime::*;
+use crate::sandbox::{empty_input, sandbox_env};
 use crate::Exception::*;
 use std::fs;
+use std::path::Path;
 
-pub fn run(file_name: &str) {
-    let mut env = default_env();
-    match exec_file(&mut env, file_name) {
+/// Runs the program in the file `file_name`. A `sandbox`ed program only gets
+/// the primitives of a sandbox environment, and no standard input.
+pub fn run(file_name: &str, sandbox: bool) {
+    let mut env = if sandbox {
+        empty_input();
+        sandbox_env()
+    } else {
+        // libraries next to the program take precedence
+        if let Some(dir) = Path::new(file_name).parent() {
+            if !dir.as_os_str().is_empty() {
+                add_search_path(dir);
+            }
+        }
+        default_env()
+    };
+    match exec_file(&mut env, &Meta::empty(), file_name) {
         Ok(()) => {}
         Err(e) => println!("{}", e),
     }
 }
 
-pub(crate) fn exec_file(env: &mut Env, file_name: &str) -> Result<(), Exn> {
-    let code = fs::read_to_string(file_name).expect(&format!("Could not read file: {}", file_name));
+/// Evaluates the file `file_name`. Failing to read it is an I/O error at `meta`.
+pub(crate) fn exec_file(env: &mut Env, meta: &Meta, file_name: &str) -> Result<(), Exn> {
+    let code = read_file(meta, file_name)?;
+    exec(env, &code, Some(file_name))?;
+    Ok(())
+}
+
+pub(crate) fn read_file(meta: &Meta, file_name: &str) -> Result<String, Exn> {
+    fs::read_to_string(file_name)
+        .map_err(|e| Exn::io(meta.clone(), &format!("could not read {}: {}", file_name, e)))
+}
+
+/// Parses all expressions in the file `file_name` without evaluating them.
+pub(crate) fn parse_file(meta: &Meta, file_name: &str) -> Result<Vec<Item>, Exn> {
+    let code = read_file(meta, file_name)?;
     let tokens = lex(&code, Some(file_name.to_owned()));
+    let mut items = Vec::new();
+    let mut rest = &tokens[..];
+    while !rest.is_empty() {
+        let (item, next) = parse(rest)?;
+        items.push(item);
+        rest = next;
+    }
+    Ok(items)
+}
+
+/// Evaluates all expressions in `code` one after the other and returns the
+/// value of the last one.
+pub(crate) fn exec(env: &mut Env, code: &str, file_name: Option<&str>) -> Result<Item, Exn> {
+    let tokens = lex(code, file_name.map(str::to_owned));
+    if tokens.is_empty() {
+        return Ok(Item::new(Meta::empty(), Exp::Unspecified));
+    }
     let mut exp_and_rest = parse(&tokens)?;
     loop {
-        eval(env, &exp_and_rest.0)?;
-        if exp_and_rest.1.len() == 0 {
-            return Ok(());
+        let result = eval(env, &exp_and_rest.0)?;
+        if exp_and_rest.1.is_empty() {
+            return Ok(result);
         }
         exp_and_rest = parse(exp_and_rest.1)?;
     }
@@ -1,27 +1,44 @@
 use crate::ast::*;
+use crate::gc::{self, Gc, Trace};
+use crate::library::BUILTIN;
 use crate::primitives::*;
+use crate::sandbox;
+use crate::symbol::Symbol;
+use crate::trace;
 use crate::Exception::*;
+use std::cell::RefCell;
 use std::collections::HashMap;
 
-#[derive(Clone)]
-pub struct Env<'a> {
-    map: HashMap<String, Item>,
-    outer: Option<&'a Env<'a>>,
+/// A single scope of variable bindings.
+pub struct Frame {
+    map: RefCell<HashMap<Symbol, Item>>,
+    outer: Option<Env>,
 }
 
-impl<'a> Env<'a> {
-    pub fn set(&mut self, key: String, val: Item) {
-        self.map.insert(key, val);
+/// A shared handle to a scope. Lambdas keep the environment they were created
+/// in, so frames can outlive the call that created them.
+#[derive(Clone)]
+pub struct Env(Gc<Frame>);
+
+impl Env {
+    pub(crate) fn new(outer: Option<Env>) -> Env {
+        Env(Gc::new(Frame {
+            map: RefCell::new(HashMap::new()),
+            outer,
+        }))
     }
 
-    pub fn get(&self, key: &str) -> Option<&Item> {
+    pub fn set(&self, key: Symbol, val: Item) {
+        self.0.map.borrow_mut().insert(key, val);
+    }
+
+    pub fn get(&self, key: Symbol) -> Option<Item> {
         let mut env = self;
         loop {
-            let x = env.map.get(key);
-            if let Some(_) = x {
-                return x;
+            if let Some(x) = env.0.map.borrow().get(&key) {
+                return Some(x.clone());
             }
-            if let Some(outer) = env.outer {
+            if let Some(outer) = &env.0.outer {
                 env = outer;
             } else {
                 return None;
@@ -29,49 +46,84 @@ impl<'a> Env<'a> {
         }
     }
 
+    pub fn set_prim(&self, name: &str, fun: Primitive) {
+        self.set(Symbol::intern(name), Item::prim(Meta::empty(), fun));
+    }
+
+    /// The outermost scope, which holds the global bindings.
+    pub fn root(&self) -> Env {
+        let mut env = self.clone();
+        while let Some(outer) = env.0.outer.clone() {
+            env = outer;
+        }
+        env
+    }
+
+    /// The names bound in this scope and the scopes around it, sorted.
+    pub fn names(&self) -> Vec<Symbol> {
+        let mut names = Vec::new();
+        let mut env = Some(self.clone());
+        while let Some(e) = env {
+            names.extend(e.0.map.borrow().keys().copied());
+            env = e.0.outer.clone();
+        }
+        names.sort_by_key(|name| name.name());
+        names.dedup();
+        names
+    }
+
     pub fn new_scope(&self) -> Env {
-        Env {
-            map: HashMap::new(),
-            outer: Some(self),
+        Env::new(Some(self.clone()))
+    }
+
+    pub(crate) fn addr(&self) -> usize {
+        self.0.addr()
+    }
+}
+
+impl Trace for Frame {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        if let Ok(map) = self.map.try_borrow() {
+            for item in map.values() {
+                item.exp.trace(visit);
+            }
+            if let Some(outer) = &self.outer {
+                visit(outer.addr());
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut map) = self.map.try_borrow_mut() {
+            map.clear();
         }
     }
+
+    fn heap_size(&self) -> usize {
+        self.map.borrow().capacity() * std::mem::size_of::<(Symbol, Item)>()
+    }
 }
 
-pub fn default_env() -> Env<'static> {
-    let mut env = Env {
-        map: HashMap::new(),
-        outer: None,
-    };
-    env.set("+".to_string(), Item::prim(Meta::empty(), prim_plus));
-    env.set("-".to_string(), Item::prim(Meta::empty(), prim_minus));
-    env.set("define".to_string(), Item::prim(Meta::empty(), prim_define));
-    env.set("lambda".to_string(), Item::prim(Meta::empty(), prim_lambda));
-    env.set("if".to_string(), Item::prim(Meta::empty(), prim_if));
-    env.set("cond".to_string(), Item::prim(Meta::empty(), prim_cond));
-    env.set("or".to_string(), Item::prim(Meta::empty(), prim_or));
-    env.set("and".to_string(), Item::prim(Meta::empty(), prim_and));
-    env.set("car".to_string(), Item::prim(Meta::empty(), prim_car));
-    env.set("cdr".to_string(), Item::prim(Meta::empty(), prim_cdr));
-    env.set("cons".to_string(), Item::prim(Meta::empty(), prim_cons));
-    env.set("list".to_string(), Item::prim(Meta::empty(), prim_list));
-    env.set("quote".to_string(), Item::prim(Meta::empty(), prim_quote));
-    env.set(
-        "display".to_string(),
-        Item::prim(Meta::empty(), prim_display),
-    );
+/// The environment programs start in, which has all built-in libraries imported.
+pub fn default_env() -> Env {
+    let env = Env::new(None);
+    for (_, prims) in BUILTIN {
+        for (name, fun) in prims.iter() {
+            env.set_prim(name, *fun);
+        }
+    }
+    env.set_prim("import", prim_import);
+    env.set_prim("define-library", prim_define_library);
     env
 }
 
-pub fn eval<'a>(env: &'a mut Env, item: &Item) -> Result<Item, Exn> {
+pub fn eval(env: &mut Env, item: &Item) -> Result<Item, Exn> {
     match item.exp.to_owned() {
-        Exp::Pair(x) => {
-            if let (Exp::Nil, Exp::Nil) = (&x.car.exp, &x.cdr.exp) {
-                Ok(Item::new(item.meta.clone(), Exp::Nil))
-            } else {
-                apply_function(env, x.clone(), item.meta.clone())
-            }
-        }
-        Exp::Symbol(s) => lookup_symbol(env, &s).ok_or(Exn::other(
+        Exp::Pair(x) => apply_function(env, x, item.meta.clone()),
+        Exp::Symbol(s) => lookup_symbol(env, s).ok_or(Exn::other(
             item.meta.clone(),
             "tried to look up undefined symbol",
         )),
@@ -82,60 +134,92 @@ pub fn eval<'a>(env: &'a mut Env, item: &Item) -> Result<Item, Exn> {
         | Exp::String(_)
         | Exp::Char(_)
         | Exp::Vector(_)
+        | Exp::HashTable(_)
+        | Exp::Record(_)
+        | Exp::RecordType(_)
+        | Exp::Port(_)
+        | Exp::Eof
+        | Exp::Unspecified
+        | Exp::Environment(_)
         | Exp::Boolean(_) => Ok(item.clone()), // self evaluating
     }
 }
 
-fn apply_function(env: &mut Env, list: LispCell, meta: Meta) -> Result<Item, Exn> {
-    match eval(env, &list.car)?.exp {
-        Exp::Primitive(prim) => Ok(prim(env, meta, *list.cdr)?),
+fn apply_function(env: &mut Env, list: Gc<LispCell>, meta: Meta) -> Result<Item, Exn> {
+    let _call = sandbox::enter(&meta)?;
+    gc::safepoint(&meta)?;
+    match eval(env, &list.car())?.exp {
+        Exp::Primitive(prim) => Ok(prim(env, meta, list.cdr())?),
         Exp::Lambda(lambda) => {
-            let mut args_iter = if let Exp::Pair(args) = list.cdr.exp {
-                eval_list(env, args)?.into_iter()
+            let args = if let Exp::Pair(args) = list.cdr().exp {
+                eval_list(env, args)?
             } else {
-                ListIter { list: None }
+                Vec::new()
             };
-            let mut scope = env.new_scope();
-            let mut num_args_found = 0;
-            for param in lambda.params.as_ref() {
-                if let Some(arg) = args_iter.next() {
-                    scope.set(param.clone(), arg);
-                    num_args_found += 1;
-                } else {
-                    return Err(Exn::arity(
-                        meta,
-                        lambda.params.len(),
-                        num_args_found,
-                    ));
-                }
-            }
-            if let Some(_) = args_iter.next() {
-                return Err(Exn::arity(
-                    meta,
-                    lambda.params.len(),
-                    num_args_found,
-                ));
-            }
-            eval(&mut scope, lambda.body.as_ref())
+            call_lambda(&lambda, args, meta)
         }
         x => Err(Exn::typ(meta, "procedure", &x.type_name())),
     }
 }
 
-pub fn eval_list(env: &mut Env, list: LispCell) -> Result<LispCell, Exn> {
-    let mut res = list;
-    let mut rest = &mut res;
-    loop {
-        rest.car = Box::new(eval(env, &mut rest.car.clone())?);
-        if let Exp::Pair(ref mut next) = rest.cdr.exp {
-            rest = next;
-        } else {
-            break;
+/// Calls the procedure `proc` with already evaluated arguments.
+pub fn apply(env: &mut Env, meta: Meta, proc: Item, args: Vec<Item>) -> Result<Item, Exn> {
+    let _call = sandbox::enter(&meta)?;
+    gc::safepoint(&meta)?;
+    match proc.exp {
+        Exp::Primitive(prim) => {
+            // primitives evaluate their arguments themselves
+            let quoted = args.into_iter().map(quote).collect();
+            prim(env, meta.clone(), Item::list(meta, quoted))
+        }
+        Exp::Lambda(lambda) => call_lambda(&lambda, args, meta),
+        x => Err(Exn::typ(proc.meta, "procedure", &x.type_name())),
+    }
+}
+
+fn call_lambda(lambda: &Lambda, args: Vec<Item>, meta: Meta) -> Result<Item, Exn> {
+    if let Some(name) = trace::name(lambda) {
+        trace::enter(&name, &args);
+        let result = call_clause(lambda, args, meta);
+        trace::exit(&result);
+        return result;
+    }
+    call_clause(lambda, args, meta)
+}
+
+fn call_clause(lambda: &Lambda, args: Vec<Item>, meta: Meta) -> Result<Item, Exn> {
+    let clause = match lambda.clauses.iter().find(|c| c.params.accepts(args.len())) {
+        Some(clause) => clause,
+        None => {
+            return Err(Exn::new(
+                meta,
+                Condition::Arity(lambda.arity(), args.len()),
+            ))
         }
+    };
+    let mut scope = lambda.env.new_scope();
+    let mut args = args.into_iter();
+    for param in &clause.params.required {
+        scope.set(*param, args.next().unwrap());
+    }
+    if let Some(rest) = clause.params.rest {
+        scope.set(rest, Item::list(meta, args.collect()));
     }
-    Ok(res)
+    eval(&mut scope, &clause.body)
+}
+
+/// Wraps `item` in a quote form. Its head is the primitive itself rather than
+/// the symbol `quote`, which the caller's environment may not bind.
+fn quote(item: Item) -> Item {
+    let meta = item.meta.clone();
+    let quote = Item::new(meta.clone(), Exp::Primitive(prim_quote));
+    Item::list(meta, vec![quote, item])
+}
+
+pub fn eval_list(env: &mut Env, list: Gc<LispCell>) -> Result<Vec<Item>, Exn> {
+    list.into_iter().map(|item| eval(env, &item)).collect()
 }
 
-fn lookup_symbol<'a>(env: &'a Env, sym: &str) -> Option<Item> {
-    env.get(sym).cloned()
+fn lookup_symbol(env: &Env, sym: Symbol) -> Option<Item> {
+    env.get(sym)
 }
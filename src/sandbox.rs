@@ -0,0 +1,174 @@
+//! Sandboxed evaluation of untrusted code.
+//!
+//! A sandbox environment only has the primitives that cannot reach outside of
+//! the interpreter: there is no file I/O, no `load` or `include`, and no
+//! libraries or environments to import the rest of the primitives from.
+//! The current input port is empty, so the readers cannot take standard input.
+//! [`Limits`] bound the work evaluation may do, and each one that is exceeded
+//! fails evaluation with its own condition.
+use crate::gc::{self, Gc};
+use crate::library::BUILTIN;
+use crate::port::{self, Port};
+use crate::runtime::*;
+use crate::Exception::*;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// The built-in libraries whose primitives a sandbox environment has.
+const LIBRARIES: [&str; 9] = [
+    "(scheme base)",
+    "(scheme write)",
+    "(scheme read)",
+    "(scheme eval)",
+    "(scheme repl)",
+    "(srfi 1)",
+    "(srfi 69)",
+    "(skim base)",
+    "(skim environment)",
+];
+
+/// Primitives of those libraries that are left out anyway: `include` reads
+/// files, `environment` can import any library and `write-simple` never
+/// returns from writing cyclic structure, without taking any steps.
+const EXCLUDED: [&str; 3] = ["include", "environment", "write-simple"];
+
+/// How many steps pass between looking at the clock.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Bounds on evaluation. `None` means unlimited.
+#[derive(Clone, Copy, Default)]
+pub struct Limits {
+    /// The number of procedure calls evaluation may make.
+    pub steps: Option<u64>,
+    /// The number of procedure calls that may be in progress at once.
+    pub depth: Option<usize>,
+    /// The heap size in bytes, as in [`gc::set_heap_limit`].
+    pub heap: Option<usize>,
+    /// The wall-clock time evaluation may take.
+    pub time: Option<Duration>,
+}
+
+impl Limits {
+    /// Limits suited for running untrusted code.
+    pub fn sandbox() -> Limits {
+        Limits {
+            steps: Some(10_000_000),
+            depth: Some(10_000),
+            heap: Some(64 << 20),
+            time: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+struct State {
+    limits: Cell<Limits>,
+    steps: Cell<u64>,
+    depth: Cell<usize>,
+    deadline: Cell<Option<Instant>>,
+}
+
+thread_local! {
+    static STATE: State = State {
+        limits: Cell::new(Limits::default()),
+        steps: Cell::new(0),
+        depth: Cell::new(0),
+        deadline: Cell::new(None),
+    };
+}
+
+/// Returns the limits evaluation is under.
+pub fn limits() -> Limits {
+    let mut limits = STATE.with(|state| state.limits.get());
+    limits.heap = gc::stats().limit;
+    limits
+}
+
+/// Puts evaluation under `limits` and restarts counting steps and time.
+pub fn set_limits(limits: &Limits) {
+    STATE.with(|state| state.limits.set(*limits));
+    gc::set_heap_limit(limits.heap);
+    restart();
+}
+
+/// Restarts counting steps and time, so that what was evaluated so far does
+/// not count against the limits any more.
+pub fn restart() {
+    STATE.with(|state| {
+        state.steps.set(0);
+        state
+            .deadline
+            .set(state.limits.get().time.map(|time| Instant::now() + time));
+    });
+}
+
+/// An environment with only the primitives that are safe to give to
+/// untrusted code.
+pub(crate) fn sandbox_env() -> Env {
+    let env = Env::new(None);
+    for (name, prims) in BUILTIN {
+        if !LIBRARIES.contains(name) {
+            continue;
+        }
+        for (name, fun) in prims.iter() {
+            if !EXCLUDED.contains(name) {
+                env.set_prim(name, *fun);
+            }
+        }
+    }
+    env
+}
+
+/// Makes an empty port the current input port, so that sandboxed code cannot
+/// read standard input, and returns the previous one.
+pub(crate) fn empty_input() -> Gc<Port> {
+    port::replace_current_input(Gc::new(Port::input_string("")))
+}
+
+/// Evaluates all expressions in `code` in a new sandbox environment under
+/// `limits`, and returns the displayed value of the last one. The previous
+/// limits and current input port are restored afterwards.
+pub fn eval_str(code: &str, limits: &Limits) -> Result<String, Exn> {
+    let previous = self::limits();
+    set_limits(limits);
+    let input = empty_input();
+    let result = crate::run::exec(&mut sandbox_env(), code, None);
+    port::replace_current_input(input);
+    set_limits(&previous);
+    result.map(|item| item.to_string())
+}
+
+/// A procedure call in progress. Dropping it ends the call.
+pub(crate) struct Call(());
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        let _ = STATE.try_with(|state| state.depth.set(state.depth.get() - 1));
+    }
+}
+
+/// Counts a procedure call at `meta` against the limits.
+pub(crate) fn enter(meta: &Meta) -> Result<Call, Exn> {
+    STATE.with(|state| {
+        let limits = state.limits.get();
+        let steps = state.steps.get() + 1;
+        state.steps.set(steps);
+        if let Some(max) = limits.steps {
+            if steps > max {
+                return Err(Exn::new(meta.clone(), Condition::StepLimit(max)));
+            }
+        }
+        if let (Some(deadline), Some(time)) = (state.deadline.get(), limits.time) {
+            if steps % CLOCK_INTERVAL == 0 && Instant::now() > deadline {
+                return Err(Exn::new(meta.clone(), Condition::TimeLimit(time)));
+            }
+        }
+        let depth = state.depth.get() + 1;
+        if let Some(max) = limits.depth {
+            if depth > max {
+                return Err(Exn::new(meta.clone(), Condition::RecursionLimit(max)));
+            }
+        }
+        state.depth.set(depth);
+        Ok(Call(()))
+    })
+}
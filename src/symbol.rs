@@ -0,0 +1,86 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
+
+/// An interned symbol name.
+///
+/// Symbols are small integer ids into a per-thread symbol table, so comparing
+/// and hashing them is as cheap as comparing and hashing a `u32`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct Symbol(u32);
+
+struct SymbolTable {
+    names: Vec<Rc<str>>,
+    ids: HashMap<Rc<str>, Symbol>,
+    gensym_counter: usize,
+}
+
+/// Symbols the interpreter itself needs to recognize, interned in this order
+/// when the table is created so that they can be used as constants.
+const KNOWN: [&str; 2] = ["quote", "else"];
+
+impl Symbol {
+    pub const QUOTE: Symbol = Symbol(0);
+    pub const ELSE: Symbol = Symbol(1);
+
+    /// Returns the symbol for `name`, adding it to the table if it is new.
+    pub fn intern(name: &str) -> Symbol {
+        SYMBOLS.with(|table| table.borrow_mut().intern(name))
+    }
+
+    /// Creates a fresh symbol that is not reachable through `intern`,
+    /// even if another symbol has the same name.
+    pub fn gensym(prefix: &str) -> Symbol {
+        SYMBOLS.with(|table| {
+            let mut table = table.borrow_mut();
+            table.gensym_counter += 1;
+            let name = format!("{}{}", prefix, table.gensym_counter);
+            table.push(name.into())
+        })
+    }
+
+    pub fn name(self) -> Rc<str> {
+        SYMBOLS.with(|table| table.borrow().names[self.0 as usize].clone())
+    }
+}
+
+impl SymbolTable {
+    fn new() -> SymbolTable {
+        let mut table = SymbolTable {
+            names: Vec::new(),
+            ids: HashMap::new(),
+            gensym_counter: 0,
+        };
+        for name in KNOWN.iter() {
+            table.intern(name);
+        }
+        table
+    }
+
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(sym) = self.ids.get(name) {
+            return *sym;
+        }
+        let name: Rc<str> = name.into();
+        let sym = self.push(name.clone());
+        self.ids.insert(name, sym);
+        sym
+    }
+
+    fn push(&mut self, name: Rc<str>) -> Symbol {
+        let sym = Symbol(self.names.len() as u32);
+        self.names.push(name);
+        sym
+    }
+}
+
+thread_local! {
+    static SYMBOLS: RefCell<SymbolTable> = RefCell::new(SymbolTable::new());
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "{}", self.name())
+    }
+}
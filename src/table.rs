@@ -0,0 +1,323 @@
+use crate::ast::*;
+use crate::gc::Trace;
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// The equivalence a hash table compares its keys with.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Equivalence {
+    Eq,
+    Eqv,
+    Equal,
+    String,
+}
+
+/// A hash table that keeps its entries in insertion order. Deleting an entry
+/// leaves a tombstone in its place, and the tombstones are swept out once
+/// they are the majority of the entries.
+pub struct HashTable {
+    pub equivalence: Equivalence,
+    entries: RefCell<Vec<Option<(Item, Item)>>>,
+    // key hash -> indices into `entries`
+    index: RefCell<HashMap<u64, Vec<usize>>>,
+    // the number of entries that are not tombstones
+    live: Cell<usize>,
+}
+
+/// How many pairs and vector elements `equal?` hashing looks at, which keeps
+/// hashing of cyclic and very large keys finite.
+const EQUAL_HASH_BUDGET: usize = 64;
+
+impl HashTable {
+    pub fn new(equivalence: Equivalence) -> HashTable {
+        HashTable {
+            equivalence,
+            entries: RefCell::new(Vec::new()),
+            index: RefCell::new(HashMap::new()),
+            live: Cell::new(0),
+        }
+    }
+
+    fn hash(&self, key: &Exp) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        match self.equivalence {
+            Equivalence::Eq | Equivalence::Eqv => hash_eqv(key, &mut hasher),
+            Equivalence::Equal | Equivalence::String => {
+                let mut budget = EQUAL_HASH_BUDGET;
+                hash_equal(key, &mut hasher, &mut budget)
+            }
+        }
+        hasher.finish()
+    }
+
+    fn same(&self, a: &Exp, b: &Exp) -> bool {
+        match self.equivalence {
+            Equivalence::Eq => a.is_eq(b),
+            Equivalence::Eqv => a.is_eqv(b),
+            Equivalence::Equal | Equivalence::String => a.is_equal(b),
+        }
+    }
+
+    /// Returns the position of `key` in `entries`.
+    fn find(&self, hash: u64, key: &Exp) -> Option<usize> {
+        let entries = self.entries.borrow();
+        let index = self.index.borrow();
+        index
+            .get(&hash)?
+            .iter()
+            .copied()
+            .find(|&i| entries[i].as_ref().is_some_and(|(k, _)| self.same(&k.exp, key)))
+    }
+
+    pub fn get(&self, key: &Exp) -> Option<Item> {
+        let i = self.find(self.hash(key), key)?;
+        self.entries.borrow()[i].as_ref().map(|(_, val)| val.clone())
+    }
+
+    pub fn contains(&self, key: &Exp) -> bool {
+        self.find(self.hash(key), key).is_some()
+    }
+
+    pub fn set(&self, key: Item, val: Item) {
+        let hash = self.hash(&key.exp);
+        if let Some(i) = self.find(hash, &key.exp) {
+            if let Some(entry) = &mut self.entries.borrow_mut()[i] {
+                entry.1 = val;
+            }
+        } else {
+            let mut entries = self.entries.borrow_mut();
+            self.index
+                .borrow_mut()
+                .entry(hash)
+                .or_default()
+                .push(entries.len());
+            entries.push(Some((key, val)));
+            self.live.set(self.live.get() + 1);
+        }
+    }
+
+    pub fn delete(&self, key: &Exp) {
+        let hash = self.hash(key);
+        let i = match self.find(hash, key) {
+            Some(i) => i,
+            None => return,
+        };
+        remove_index(&mut self.index.borrow_mut(), hash, i);
+        self.entries.borrow_mut()[i] = None;
+        self.live.set(self.live.get() - 1);
+        if self.live.get() * 2 < self.entries.borrow().len() {
+            self.compact();
+        }
+    }
+
+    /// Removes the tombstones, which moves the entries after them, and
+    /// rebuilds the index for their new positions.
+    fn compact(&self) {
+        let mut entries = self.entries.borrow_mut();
+        entries.retain(Option::is_some);
+        let mut index = self.index.borrow_mut();
+        index.clear();
+        for (i, (key, _)) in entries.iter().flatten().enumerate() {
+            index.entry(self.hash(&key.exp)).or_default().push(i);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.live.get()
+    }
+
+    /// A snapshot of the entries, which stays valid while the table is changed.
+    pub fn entries(&self) -> Vec<(Item, Item)> {
+        self.entries.borrow().iter().flatten().cloned().collect()
+    }
+}
+
+fn remove_index(index: &mut HashMap<u64, Vec<usize>>, hash: u64, i: usize) {
+    if let Some(positions) = index.get_mut(&hash) {
+        positions.retain(|&pos| pos != i);
+        if positions.is_empty() {
+            index.remove(&hash);
+        }
+    }
+}
+
+/// Hashes `exp` consistently with `eqv?`.
+fn hash_eqv(exp: &Exp, hasher: &mut DefaultHasher) {
+    std::mem::discriminant(exp).hash(hasher);
+    match exp {
+        Exp::Nil | Exp::Eof | Exp::Unspecified => {}
+        Exp::Boolean(b) => b.hash(hasher),
+        Exp::Number(n) => n.to_bits().hash(hasher),
+        Exp::Char(c) => c.hash(hasher),
+        Exp::Symbol(s) => s.hash(hasher),
+        Exp::String(s) => s.addr().hash(hasher),
+        Exp::Vector(v) => v.addr().hash(hasher),
+        Exp::Pair(p) => p.addr().hash(hasher),
+        Exp::Lambda(l) => l.addr().hash(hasher),
+        Exp::HashTable(t) => t.addr().hash(hasher),
+        Exp::Record(r) => r.addr().hash(hasher),
+        Exp::RecordType(r) => r.addr().hash(hasher),
+        Exp::Port(p) => p.addr().hash(hasher),
+        Exp::Environment(e) => e.addr().hash(hasher),
+        Exp::Primitive(p) => (*p as usize).hash(hasher),
+    }
+}
+
+/// Hashes `exp` consistently with `equal?`, looking at no more than `budget`
+/// pairs and vector elements.
+fn hash_equal(exp: &Exp, hasher: &mut DefaultHasher, budget: &mut usize) {
+    match exp {
+        Exp::Pair(cell) => {
+            std::mem::discriminant(exp).hash(hasher);
+            if *budget == 0 {
+                return;
+            }
+            *budget -= 1;
+            hash_equal(&cell.car().exp, hasher, budget);
+            hash_equal(&cell.cdr().exp, hasher, budget);
+        }
+        Exp::Vector(vec) => {
+            std::mem::discriminant(exp).hash(hasher);
+            let vec = vec.borrow();
+            vec.len().hash(hasher);
+            for x in vec.iter() {
+                if *budget == 0 {
+                    return;
+                }
+                *budget -= 1;
+                hash_equal(x, hasher, budget);
+            }
+        }
+        Exp::String(s) => {
+            std::mem::discriminant(exp).hash(hasher);
+            s.hash(hasher);
+        }
+        _ => hash_eqv(exp, hasher),
+    }
+}
+
+impl Trace for HashTable {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        if let Ok(entries) = self.entries.try_borrow() {
+            for (key, val) in entries.iter().flatten() {
+                key.exp.trace(visit);
+                val.exp.trace(visit);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn clear(&self) {
+        if let (Ok(mut entries), Ok(mut index)) =
+            (self.entries.try_borrow_mut(), self.index.try_borrow_mut())
+        {
+            entries.clear();
+            index.clear();
+            self.live.set(0);
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        self.entries.borrow().capacity() * std::mem::size_of::<Option<(Item, Item)>>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Exception::Meta;
+
+    fn number(n: usize) -> Item {
+        Item::new(Meta::empty(), Exp::Number(n as f64))
+    }
+
+    fn keys(table: &HashTable) -> Vec<Exp> {
+        table.entries().into_iter().map(|(key, _)| key.exp).collect()
+    }
+
+    fn numbers(ns: &[usize]) -> Vec<Exp> {
+        ns.iter().map(|&n| number(n).exp).collect()
+    }
+
+    /// A list of `ns` whose last pair points back to the first one.
+    fn cycle(ns: &[usize]) -> Item {
+        let list = Item::list(Meta::empty(), ns.iter().map(|&n| number(n)).collect());
+        let mut last = match &list.exp {
+            Exp::Pair(cell) => cell.clone(),
+            _ => unreachable!(),
+        };
+        while let Exp::Pair(next) = last.cdr().exp {
+            last = next;
+        }
+        last.set_cdr(list.clone());
+        list
+    }
+
+    #[test]
+    fn delete_keeps_insertion_order() {
+        let table = HashTable::new(Equivalence::Eqv);
+        for n in 0..5 {
+            table.set(number(n), number(n * 10));
+        }
+        table.delete(&number(1).exp);
+        assert_eq!(table.len(), 4);
+        assert!(table.get(&number(1).exp).is_none());
+        assert!(table.get(&number(4).exp).is_some_and(|val| val.exp.is_eqv(&number(40).exp)));
+        // a key that is set again goes to the end
+        table.set(number(1), number(11));
+        let expected = numbers(&[0, 2, 3, 4, 1]);
+        assert!(keys(&table).iter().zip(&expected).all(|(a, b)| a.is_eqv(b)));
+    }
+
+    #[test]
+    fn compaction_keeps_insertion_order() {
+        let table = HashTable::new(Equivalence::Eqv);
+        for n in 0..10 {
+            table.set(number(n), number(n));
+        }
+        // more than half of the entries become tombstones, which compacts
+        for n in [0, 2, 3, 5, 6, 8] {
+            table.delete(&number(n).exp);
+        }
+        assert_eq!(table.entries.borrow().len(), 4);
+        let expected = numbers(&[1, 4, 7, 9]);
+        assert_eq!(keys(&table).len(), expected.len());
+        assert!(keys(&table).iter().zip(&expected).all(|(a, b)| a.is_eqv(b)));
+        // the index points at the new positions
+        for n in [1, 4, 7, 9] {
+            assert!(table.get(&number(n).exp).is_some_and(|val| val.exp.is_eqv(&number(n).exp)));
+        }
+        table.set(number(0), number(0));
+        assert!(keys(&table).last().is_some_and(|key| key.is_eqv(&number(0).exp)));
+    }
+
+    #[test]
+    fn cyclic_keys_hash_within_budget() {
+        let table = HashTable::new(Equivalence::Equal);
+        table.set(cycle(&[1, 2]), number(12));
+        // an equal but separately built cycle finds the entry
+        let other = cycle(&[1, 2]);
+        assert!(table.get(&other.exp).is_some_and(|val| val.exp.is_eqv(&number(12).exp)));
+        assert!(table.get(&cycle(&[1, 3]).exp).is_none());
+    }
+
+    #[test]
+    fn equal_hash_stops_at_budget() {
+        let table = HashTable::new(Equivalence::Equal);
+        let long = |last: usize| {
+            let mut ns: Vec<usize> = vec![0; EQUAL_HASH_BUDGET * 2];
+            ns.push(last);
+            Item::list(Meta::empty(), ns.into_iter().map(number).collect())
+        };
+        // lists that differ only past the budget hash the same, but are still
+        // told apart by equal?
+        assert_eq!(table.hash(&long(1).exp), table.hash(&long(2).exp));
+        table.set(long(1), number(1));
+        assert!(table.get(&long(2).exp).is_none());
+        assert!(table.get(&long(1).exp).is_some());
+    }
+}
@@ -0,0 +1,93 @@
+//! Tracing of procedure calls, which the REPL turns on with `,trace`.
+//!
+//! Calls of traced procedures are written to the current error port as they
+//! happen, with their arguments, and so are the values they return, indented
+//! by how deeply the traced calls are nested.
+use crate::ast::*;
+use crate::gc::Gc;
+use crate::port;
+use crate::Exception::*;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+thread_local! {
+    // the traced lambdas by address, with the names they are traced under;
+    // holding on to them keeps their addresses from being reused
+    static TRACED: RefCell<HashMap<usize, (Gc<Lambda>, String)>> = RefCell::new(HashMap::new());
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+fn key(lambda: &Lambda) -> usize {
+    lambda as *const Lambda as usize
+}
+
+pub(crate) fn trace(lambda: &Gc<Lambda>, name: &str) {
+    TRACED.with(|traced| {
+        traced
+            .borrow_mut()
+            .insert(key(lambda), (lambda.clone(), name.to_string()))
+    });
+}
+
+/// Stops tracing `lambda`. Returns false if it was not traced.
+pub(crate) fn untrace(lambda: &Lambda) -> bool {
+    TRACED.with(|traced| traced.borrow_mut().remove(&key(lambda)).is_some())
+}
+
+pub(crate) fn untrace_all() {
+    TRACED.with(|traced| traced.borrow_mut().clear());
+}
+
+/// The names of the traced procedures, sorted.
+pub(crate) fn traced() -> Vec<String> {
+    let mut names: Vec<String> = TRACED.with(|traced| {
+        traced
+            .borrow()
+            .values()
+            .map(|(_, name)| name.clone())
+            .collect()
+    });
+    names.sort();
+    names
+}
+
+/// The name `lambda` is traced under, if it is traced.
+pub(crate) fn name(lambda: &Lambda) -> Option<String> {
+    TRACED.with(|traced| {
+        let traced = traced.borrow();
+        if traced.is_empty() {
+            return None;
+        }
+        traced.get(&key(lambda)).map(|(_, name)| name.clone())
+    })
+}
+
+fn write_line(depth: usize, line: &str) {
+    let indent = "  ".repeat(depth);
+    // tracing must not change what the program does, so errors are ignored
+    let _ = port::current_error().write_str(&format!("{}{}\n", indent, line));
+}
+
+/// Writes the call of the procedure traced as `name` with `args`.
+pub(crate) fn enter(name: &str, args: &[Item]) {
+    let depth = DEPTH.with(|depth| depth.replace(depth.get() + 1));
+    let mut call = format!("> ({}", name);
+    for arg in args {
+        call.push(' ');
+        call.push_str(&arg.exp.written());
+    }
+    call.push(')');
+    write_line(depth, &call);
+}
+
+/// Writes what the call that was entered last returned.
+pub(crate) fn exit(result: &Result<Item, Exn>) {
+    let depth = DEPTH.with(|depth| {
+        depth.set(depth.get() - 1);
+        depth.get()
+    });
+    match result {
+        Ok(value) => write_line(depth, &format!("< {}", value.exp.written())),
+        Err(_) => write_line(depth, "< raised an exception"),
+    }
+}
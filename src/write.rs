@@ -0,0 +1,281 @@
+//! The external representations of values, as `write` and `display` show them
+//! (R7RS 6.13.3).
+//!
+//! Objects that are shared are shown with datum labels: the first time one is
+//! written it is marked with `#0=`, and every later time only `#0#` refers back
+//! to it. `write` and `display` label the objects that are part of a cycle, so
+//! that showing cyclic structure terminates, `write-shared` labels everything
+//! that is shared and `write-simple` labels nothing.
+use crate::ast::*;
+use crate::gc::Gc;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Sharing {
+    None,
+    Cycles,
+    All,
+}
+
+/// A datum label that is written in front of an object or instead of it.
+pub(crate) enum Label {
+    /// `#0=`, the first time the object is written.
+    Define(usize),
+    /// `#0#`, every other time.
+    Reference(usize),
+}
+
+impl Display for Label {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Label::Define(n) => write!(f, "#{}=", n),
+            Label::Reference(n) => write!(f, "#{}#", n),
+        }
+    }
+}
+
+/// The objects of a value that are labelled, with the numbers they were
+/// given once they have been written.
+pub(crate) struct Labels {
+    labels: HashMap<usize, Option<usize>>,
+    next: usize,
+}
+
+/// The address of the heap object `exp` is, if it can contain other values
+/// and so be part of a cycle.
+fn addr(exp: &Exp) -> Option<usize> {
+    match exp {
+        Exp::Pair(cell) => Some(cell.addr()),
+        Exp::Vector(vec) => Some(vec.addr()),
+        Exp::HashTable(table) => Some(table.addr()),
+        Exp::Record(record) => Some(record.addr()),
+        _ => None,
+    }
+}
+
+/// The values `exp` contains.
+fn children(exp: &Exp) -> Vec<Exp> {
+    match exp {
+        Exp::Pair(cell) => vec![cell.car().exp, cell.cdr().exp],
+        Exp::Vector(vec) => vec.borrow().clone(),
+        Exp::HashTable(table) => table
+            .entries()
+            .into_iter()
+            .flat_map(|(key, val)| vec![key.exp, val.exp])
+            .collect(),
+        Exp::Record(record) => (0..record.rtd.fields.len())
+            .map(|i| record.get(i).exp)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+enum Visit {
+    Enter(Exp),
+    Leave(usize),
+}
+
+impl Labels {
+    /// Finds the objects in `exp` to label. The walk keeps its own stack, so
+    /// long lists don't overflow the rust stack.
+    pub fn new(exp: &Exp, sharing: Sharing) -> Labels {
+        let mut labels = HashMap::new();
+        if sharing != Sharing::None {
+            // true while an object's children are walked, false after
+            let mut walking: HashMap<usize, bool> = HashMap::new();
+            let mut stack = vec![Visit::Enter(exp.clone())];
+            while let Some(visit) = stack.pop() {
+                let exp = match visit {
+                    Visit::Enter(exp) => exp,
+                    Visit::Leave(addr) => {
+                        walking.insert(addr, false);
+                        continue;
+                    }
+                };
+                let addr = match addr(&exp) {
+                    Some(addr) => addr,
+                    None => continue,
+                };
+                match walking.get(&addr) {
+                    // reached again from inside itself, so it is in a cycle
+                    Some(true) => {
+                        labels.insert(addr, None);
+                    }
+                    Some(false) if sharing == Sharing::All => {
+                        labels.insert(addr, None);
+                    }
+                    Some(false) => {}
+                    None => {
+                        walking.insert(addr, true);
+                        stack.push(Visit::Leave(addr));
+                        let children = children(&exp);
+                        stack.extend(children.into_iter().rev().map(Visit::Enter));
+                    }
+                }
+            }
+        }
+        Labels { labels, next: 0 }
+    }
+
+    pub fn is_labelled(&self, exp: &Exp) -> bool {
+        addr(exp).is_some_and(|addr| self.labels.contains_key(&addr))
+    }
+
+    /// The label to write for `exp` in the place it is written next, if it is
+    /// labelled.
+    pub fn label(&mut self, exp: &Exp) -> Option<Label> {
+        let label = self.labels.get_mut(&addr(exp)?)?;
+        match *label {
+            Some(n) => Some(Label::Reference(n)),
+            None => {
+                *label = Some(self.next);
+                self.next += 1;
+                Some(Label::Define(self.next - 1))
+            }
+        }
+    }
+}
+
+/// Shows `exp` as `write` shows it if `write` is true and as `display` does
+/// otherwise, labelling the objects in `labels`.
+pub(crate) fn format(exp: &Exp, write: bool, labels: &mut Labels) -> String {
+    let mut writer = Writer {
+        write,
+        labels,
+        out: String::new(),
+    };
+    writer.exp(exp);
+    writer.out
+}
+
+/// Shows `exp` with the objects labelled that `sharing` asks for.
+pub(crate) fn to_string(exp: &Exp, write: bool, sharing: Sharing) -> String {
+    format(exp, write, &mut Labels::new(exp, sharing))
+}
+
+struct Writer<'l> {
+    write: bool,
+    labels: &'l mut Labels,
+    out: String,
+}
+
+impl Writer<'_> {
+    fn exp(&mut self, exp: &Exp) {
+        match self.labels.label(exp) {
+            Some(label @ Label::Reference(_)) => return self.out.push_str(&label.to_string()),
+            Some(label) => self.out.push_str(&label.to_string()),
+            None => {}
+        }
+        match exp {
+            Exp::Pair(cell) => self.list(cell),
+            Exp::Vector(vec) => {
+                self.out.push_str("#(");
+                self.items(&vec.borrow());
+                self.out.push(')');
+            }
+            Exp::HashTable(table) => {
+                self.out.push_str("#<hash-table");
+                for (key, val) in table.entries() {
+                    self.out.push_str(" (");
+                    self.exp(&key.exp);
+                    self.out.push_str(" . ");
+                    self.exp(&val.exp);
+                    self.out.push(')');
+                }
+                self.out.push('>');
+            }
+            Exp::Record(record) => {
+                self.out.push_str(&format!("#<{}", record.rtd.name));
+                for (i, name) in record.rtd.fields.iter().enumerate() {
+                    self.out.push_str(&format!(" {}: ", name));
+                    self.exp(&record.get(i).exp);
+                }
+                self.out.push('>');
+            }
+            x => self.out.push_str(&atom(x, self.write)),
+        }
+    }
+
+    fn items(&mut self, items: &[Exp]) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push(' ');
+            }
+            self.exp(item);
+        }
+    }
+
+    fn list(&mut self, cell: &Gc<LispCell>) {
+        self.out.push('(');
+        self.exp(&cell.car().exp);
+        let mut rest = cell.cdr().exp;
+        loop {
+            match rest {
+                // a labelled tail has to be written as the tail of a dotted
+                // list to get its label
+                Exp::Pair(cell) if !self.labels.is_labelled(&rest) => {
+                    self.out.push(' ');
+                    self.exp(&cell.car().exp);
+                    rest = cell.cdr().exp;
+                }
+                Exp::Nil => break,
+                x => {
+                    self.out.push_str(" . ");
+                    self.exp(&x);
+                    break;
+                }
+            }
+        }
+        self.out.push(')');
+    }
+}
+
+/// Shows a value that contains no other values.
+fn atom(exp: &Exp, write: bool) -> String {
+    match exp {
+        Exp::Nil => "()".to_string(),
+        Exp::Number(s) => s.to_string(),
+        Exp::Symbol(s) => s.to_string(),
+        Exp::Primitive(_) => "primitive function".to_string(),
+        Exp::Lambda(_) => "lambda function".to_string(),
+        Exp::Boolean(b) => if *b { "#t" } else { "#f" }.to_string(),
+        Exp::Char(c) if write => write_char(*c),
+        Exp::Char(c) => c.to_string(),
+        Exp::String(s) if write => write_string(s),
+        Exp::String(s) => s.to_string(),
+        Exp::RecordType(rtd) => format!("#<record-type {}>", rtd.name),
+        Exp::Port(port) => port.format(),
+        Exp::Eof => "#<eof>".to_string(),
+        Exp::Unspecified => "#<unspecified>".to_string(),
+        Exp::Environment(_) => "#<environment>".to_string(),
+        Exp::Pair(_) | Exp::Vector(_) | Exp::HashTable(_) | Exp::Record(_) => {
+            to_string(exp, write, Sharing::Cycles)
+        }
+    }
+}
+
+fn write_char(c: char) -> String {
+    match CHAR_NAMES.iter().find(|(_, named)| *named == c) {
+        Some((name, _)) => format!("#\\{}", name),
+        None if c.is_control() => format!("#\\x{:x}", c as u32),
+        None => format!("#\\{}", c),
+    }
+}
+
+fn write_string(s: &str) -> String {
+    let mut written = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => written.push_str("\\\""),
+            '\\' => written.push_str("\\\\"),
+            '\n' => written.push_str("\\n"),
+            '\t' => written.push_str("\\t"),
+            '\r' => written.push_str("\\r"),
+            '\0' => written.push_str("\\0"),
+            c => written.push(c),
+        }
+    }
+    written.push('"');
+    written
+}
@@ -0,0 +1,178 @@
+//! Runs the `skim-lisp` binary the way it is used from the shell.
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn skim_lisp(args: &[&str], dir: &PathBuf, stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_skim-lisp"))
+        .args(args)
+        .current_dir(dir)
+        // the REPL keeps its history in the home directory
+        .env("HOME", dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// A fresh directory for the files of the test `name`.
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("skim-lisp-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn scripts_named_like_the_fmt_subcommand_run() {
+    let dir = scratch("scripts");
+    for name in ["fmt.scm", "fmt3.scm", "fmtx"] {
+        fs::write(dir.join(name), format!("(display \"{}\")", name)).unwrap();
+        let output = skim_lisp(&[name], &dir, "");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(String::from_utf8_lossy(&output.stdout), name);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn fmt_subcommand_formats_standard_input() {
+    let dir = scratch("fmt");
+    let output = skim_lisp(&["fmt"], &dir, "(a   b)");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "(a b)\n");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn sandboxed_scripts_do_not_read_standard_input() {
+    let dir = scratch("sandbox");
+    let script = "(write (list (read-line) (read-char) (peek-char) (read) (read-all)))";
+    fs::write(dir.join("read.scm"), script).unwrap();
+    let output = skim_lisp(&["--sandbox", "read.scm"], &dir, "secret\n(data)\n");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "(#<eof> #<eof> #<eof> #<eof> ())"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn pretty_print_takes_a_width_without_a_port() {
+    let dir = scratch("pretty-print");
+    let script =
+        "(pretty-print '(aaaa bbbb cccc) 10) (pretty-print '(a b) (current-output-port) 10)";
+    fs::write(dir.join("pp.scm"), script).unwrap();
+    let output = skim_lisp(&["pp.scm"], &dir, "");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "(aaaa bbbb\n      cccc)\n(a b)\n"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn written_data_read_back_the_same() {
+    let dir = scratch("write");
+    let script = r#"
+(define (written x)
+  ((lambda (port) (begin (write x port) (get-output-string port)))
+   (open-output-string)))
+(define (round-trip x)
+  (begin
+    (display (written x))
+    (display " => ")
+    (display (written (string->datum (written x))))
+    (newline)))
+(round-trip (list->string (list #\a #\" #\\ #\newline #\tab #\return #\null #\x7)))
+(round-trip (list #\a #\space #\x0 #\x1 #\x1f #\x7f #\x3bb #\( #\;))
+(round-trip #(1 "two" #\3 (4 #(5)) #()))
+(define cycle (list 1 2 3))
+(set-cdr! (cdr (cdr cycle)) cycle)
+(round-trip cycle)
+(round-trip (list cycle cycle))
+"#;
+    fs::write(dir.join("write.scm"), script).unwrap();
+    let output = skim_lisp(&["write.scm"], &dir, "");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let written: Vec<&str> = stdout
+        .lines()
+        .map(|line| {
+            let (before, after) = line.split_once(" => ").unwrap();
+            assert_eq!(before, after);
+            before
+        })
+        .collect();
+    assert_eq!(
+        written,
+        [
+            "\"a\\\"\\\\\\n\\t\\r\\0\u{7}\"",
+            "(#\\a #\\space #\\null #\\x1 #\\x1f #\\delete #\\λ #\\( #\\;)",
+            "#(1 \"two\" #\\3 (4 #(5)) #())",
+            "#0=(1 2 3 . #0#)",
+            "(#0=(1 2 3 . #0#) #0#)",
+        ]
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn fmt_keeps_inline_comments_inline() {
+    let dir = scratch("fmt-comments");
+    let formatted = [
+        "(g 1 #;2 3)\n",
+        "(h #;(x) y)\n",
+        "(f a #| c |# b)\n",
+        "(define x 1) #;(old) ; note\n(y)\n",
+    ];
+    for source in formatted {
+        let output = skim_lisp(&["fmt"], &dir, source);
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), source);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn fmt_is_idempotent() {
+    let dir = scratch("fmt-idempotent");
+    let source =
+        "; a program\n\n(define (f x) #| the x |# (g x #;(h x)\n  1))\n\n\n(f ;; c\n #;2 3)\n";
+    let once = skim_lisp(&["fmt"], &dir, source);
+    assert!(once.status.success());
+    let once = String::from_utf8_lossy(&once.stdout).into_owned();
+    let twice = skim_lisp(&["fmt"], &dir, &once);
+    assert_eq!(String::from_utf8_lossy(&twice.stdout), once);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn heap_limit_fails_evaluation_without_ending_the_session() {
+    let dir = scratch("heap-limit");
+    let input = "(define (grow l n) (if (eq? n 0) l (grow (cons l l) (- n 1))))
+(define (loop l) (loop (cons (grow l 9) l)))
+(loop '())
+(display \"still here\")
+";
+    let output = skim_lisp(&["--no-init", "--heap-limit", "1000000"], &dir, input);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let error = stdout.find("heap limit of 1000000 bytes exceeded").unwrap();
+    assert!(stdout[error..].contains("still here"));
+    fs::remove_dir_all(dir).unwrap();
+}